@@ -0,0 +1,146 @@
+//! Administration of the ProvingManager contract: proof configurations and
+//! roles.
+//!
+//! Changing calls are simulated with `eth_call` before being sent, so that a
+//! missing role or an unknown configuration is reported without spending gas.
+//! In dry-run mode only the target address and calldata are printed.
+
+use crate::{
+    contracts::ProvingManager,
+    rpc::RpcPool,
+    types::{Args, ManagerAction, ManagerRole},
+    zk::compute_vkey,
+};
+use alloy::{
+    hex,
+    network::{ReceiptResponse, TransactionBuilder},
+    primitives::{Address, FixedBytes, U256},
+    providers::{DynProvider, Provider, ProviderBuilder},
+    rpc::types::TransactionRequest,
+    signers::local::PrivateKeySigner,
+    sol_types::SolCall,
+};
+use anyhow::anyhow;
+use std::str::FromStr;
+
+pub fn signer_from_args(args: &Args) -> Result<PrivateKeySigner, anyhow::Error> {
+    let key = args
+        .private_key
+        .as_deref()
+        .ok_or(anyhow!("--private-key (or PRIVATE_KEY) is required to send transactions"))?;
+    Ok(PrivateKeySigner::from_str(key)?)
+}
+
+/// Print the planned call in dry-run mode, otherwise simulate and send it
+/// signed with `--private-key`.
+pub async fn submit(
+    rpc: &RpcPool,
+    args: &Args,
+    to: Address,
+    calldata: Vec<u8>,
+    dry_run: bool,
+) -> Result<(), anyhow::Error> {
+    if dry_run {
+        println!("to: {to}");
+        println!("calldata: 0x{}", hex::encode(&calldata));
+        return Ok(());
+    }
+    let signer = signer_from_args(args)?;
+    let from = signer.address();
+    let provider = ProviderBuilder::new()
+        .wallet(signer)
+        .connect_provider(rpc.provider().await?);
+    let tx = TransactionRequest::default()
+        .with_from(from)
+        .with_to(to)
+        .with_input(calldata);
+    provider
+        .call(tx.clone())
+        .await
+        .map_err(|err| anyhow!("call would revert: {err}"))?;
+    let pending = provider.send_transaction(tx).await?;
+    println!("sent: {}", pending.tx_hash());
+    let receipt = pending.get_receipt().await?;
+    if !receipt.status() {
+        return Err(anyhow!("transaction {} reverted", receipt.transaction_hash()));
+    }
+    println!("confirmed in block {}", receipt.block_number().unwrap_or_default());
+    Ok(())
+}
+
+async fn manager_instance(
+    rpc: &RpcPool,
+    args: &Args,
+) -> Result<ProvingManager::ProvingManagerInstance<DynProvider>, anyhow::Error> {
+    Ok(ProvingManager::new(args.manager_address, rpc.provider().await?))
+}
+
+async fn role_hash(
+    manager: &ProvingManager::ProvingManagerInstance<DynProvider>,
+    role: ManagerRole,
+) -> Result<FixedBytes<32>, anyhow::Error> {
+    Ok(match role {
+        ManagerRole::Configurator => manager.CONFIGURATOR_ROLE().call().await?,
+        ManagerRole::Admin => manager.DEFAULT_ADMIN_ROLE().call().await?,
+    })
+}
+
+pub async fn run_manager_action(
+    action: ManagerAction,
+    dry_run: bool,
+    args: &Args,
+) -> Result<(), anyhow::Error> {
+    let rpc = RpcPool::from_args(args);
+    let to = args.manager_address;
+    match action {
+        ManagerAction::Vkey => println!("{}", compute_vkey(&args.program_path)?),
+        ManagerAction::ShowConfig { name } => {
+            let manager = manager_instance(&rpc, args).await?;
+            let config = manager.getConfiguration(name.clone()).call().await?;
+            if config.VKey == FixedBytes::ZERO {
+                return Err(anyhow!("Configuration {name} does not exist"));
+            }
+            println!("vkey: {}", config.VKey);
+            println!("samples: {}", config.numberOfSamples);
+        }
+        ManagerAction::AddConfig {
+            name,
+            samples,
+            vkey,
+        } => {
+            let vkey = match vkey {
+                Some(vkey) => vkey,
+                None => FixedBytes::<32>::from_str(&compute_vkey(&args.program_path)?)?,
+            };
+            println!("vkey: {vkey}");
+            let calldata = ProvingManager::addConfigurationCall {
+                config_name: name,
+                VKey: vkey,
+                numberOfSamples: U256::from(samples),
+            }
+            .abi_encode();
+            submit(&rpc, args, to, calldata, dry_run).await?;
+        }
+        ManagerAction::RemoveConfig { name } => {
+            let calldata =
+                ProvingManager::removeConfigurationCall { config_name: name }.abi_encode();
+            submit(&rpc, args, to, calldata, dry_run).await?;
+        }
+        ManagerAction::HasRole { account, role } => {
+            let manager = manager_instance(&rpc, args).await?;
+            let role = role_hash(&manager, role).await?;
+            println!("{}", manager.hasRole(role, account).call().await?);
+        }
+        ManagerAction::GrantRole { account, role } => {
+            let role = role_hash(&manager_instance(&rpc, args).await?, role).await?;
+            let calldata = ProvingManager::grantRoleCall { role, account }.abi_encode();
+            submit(&rpc, args, to, calldata, dry_run).await?;
+        }
+        ManagerAction::RevokeRole { account, role } => {
+            let role = role_hash(&manager_instance(&rpc, args).await?, role).await?;
+            let calldata = ProvingManager::revokeRoleCall { role, account }.abi_encode();
+            submit(&rpc, args, to, calldata, dry_run).await?;
+        }
+    }
+    Ok(())
+}
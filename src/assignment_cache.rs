@@ -0,0 +1,242 @@
+//! Content-addressed on-disk cache of compressed assignment files.
+//!
+//! Blobs are stored as `<keccak256 of content>.fb.gz` inside the cache
+//! directory, and `index.tsv` maps every source (URL or path) to the hash of
+//! its content.  A blob's modification time doubles as its last-access time
+//! for LRU eviction, and its content is re-hashed on every read.
+//!
+//! Downloads are streamed to a temporary file and into the decoder at the same
+//! time, so neither path keeps the compressed file in memory.  All file access,
+//! hashing and decompression runs on the blocking pool.
+
+use crate::{
+    assignment_source::{AssignmentDecoder, AssignmentLocation, DecodedAssignment, copy_chunks},
+    store::write_atomic,
+};
+use alloy::hex;
+use anyhow::anyhow;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tiny_keccak::{Hasher, Keccak};
+use tracing::{info, warn};
+
+const INDEX_FILE: &str = "index.tsv";
+const BLOB_SUFFIX: &str = ".fb.gz";
+
+/// Cheap to clone; clones share the index.
+#[derive(Clone)]
+pub struct AssignmentCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// source -> content hash (hex)
+    index: Arc<Mutex<HashMap<String, String>>>,
+}
+
+fn finalize_hash(keccak: Keccak) -> String {
+    let mut out = [0u8; 32];
+    keccak.finalize(&mut out);
+    hex::encode(out)
+}
+
+/// Keccak-256 of a file, computed without loading it into memory.
+fn file_hash(path: &Path) -> Result<String, anyhow::Error> {
+    let mut keccak = Keccak::v256();
+    copy_chunks(&mut File::open(path)?, |chunk| {
+        keccak.update(chunk);
+        Ok(())
+    })?;
+    Ok(finalize_hash(keccak))
+}
+
+fn decode_file(path: &Path, limit: u64) -> Result<DecodedAssignment, anyhow::Error> {
+    let mut decoder = AssignmentDecoder::new(limit);
+    copy_chunks(&mut File::open(path)?, |chunk| decoder.feed(chunk))?;
+    decoder.finish(true)
+}
+
+impl AssignmentCache {
+    /// Open (or create) a cache in `dir`.  A `max_bytes` of 0 disables caching.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> Result<Self, anyhow::Error> {
+        let dir = dir.into();
+        let mut index = HashMap::new();
+        if max_bytes > 0 {
+            fs::create_dir_all(&dir)?;
+            if let Ok(content) = fs::read_to_string(dir.join(INDEX_FILE)) {
+                for line in content.lines() {
+                    if let Some((hash, source)) = line.split_once('\t') {
+                        index.insert(source.to_owned(), hash.to_owned());
+                    }
+                }
+            }
+        }
+        Ok(AssignmentCache {
+            dir,
+            max_bytes,
+            index: Arc::new(Mutex::new(index)),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    /// Decompress the assignment at `location`, from the cache if a valid copy
+    /// exists, otherwise streamed from the source and stored on the way.
+    pub async fn load(
+        &self,
+        location: &AssignmentLocation,
+        limit: u64,
+    ) -> Result<DecodedAssignment, anyhow::Error> {
+        if !self.is_enabled() {
+            return location.load(limit).await;
+        }
+        let source = location.to_string();
+        let hit = {
+            let cache = self.clone();
+            let source = source.clone();
+            tokio::task::spawn_blocking(move || {
+                let path = cache.lookup(&source)?;
+                Some(decode_file(&path, limit))
+            })
+            .await?
+        };
+        if let Some(decoded) = hit {
+            return decoded;
+        }
+
+        let cache = self.clone();
+        location
+            .read_blocking(move |reader| cache.download(reader, &source, limit))
+            .await
+    }
+
+    /// Copy `reader` to a temporary file while hashing and decompressing it,
+    /// then move the file into the cache.
+    fn download(
+        &self,
+        reader: &mut dyn Read,
+        source: &str,
+        limit: u64,
+    ) -> Result<DecodedAssignment, anyhow::Error> {
+        let tmp = self.dir.join(format!("{}.tmp", uuid::Uuid::new_v4()));
+        let mut keccak = Keccak::v256();
+        let mut decoder = AssignmentDecoder::new(limit);
+        let decoded = File::create(&tmp)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut tmp_file| {
+                copy_chunks(reader, |chunk| {
+                    tmp_file.write_all(chunk)?;
+                    keccak.update(chunk);
+                    decoder.feed(chunk)
+                })
+            })
+            .and_then(|()| decoder.finish(false));
+        let decoded = match decoded {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(err);
+            }
+        };
+        if let Err(err) = self.store(source, &tmp, finalize_hash(keccak)) {
+            warn!("assignment cache: failed to store {source}: {err:?}");
+            let _ = fs::remove_file(&tmp);
+        }
+        Ok(decoded)
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{hash}{BLOB_SUFFIX}"))
+    }
+
+    /// Path of a valid cached blob for `source`.  Blobs failing the integrity
+    /// check are removed.  The blob is hashed without holding the index lock.
+    fn lookup(&self, source: &str) -> Option<PathBuf> {
+        let hash = self.index.lock().unwrap().get(source)?.clone();
+        let path = self.blob_path(&hash);
+        match file_hash(&path) {
+            Ok(actual) if actual == hash => {
+                if let Ok(file) = File::options().write(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                Some(path)
+            }
+            Ok(_) => {
+                warn!("assignment cache: integrity check failed for {source}, dropping {hash}");
+                let _ = fs::remove_file(&path);
+                let mut index = self.index.lock().unwrap();
+                index.retain(|_, h| *h != hash);
+                if let Err(err) = self.write_index(&index) {
+                    warn!("assignment cache: failed to write index: {err:?}");
+                }
+                None
+            }
+            Err(_) => {
+                let mut index = self.index.lock().unwrap();
+                if index.get(source) == Some(&hash) {
+                    index.remove(source);
+                    if let Err(err) = self.write_index(&index) {
+                        warn!("assignment cache: failed to write index: {err:?}");
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    fn store(&self, source: &str, tmp: &Path, hash: String) -> Result<(), anyhow::Error> {
+        let path = self.blob_path(&hash);
+        if path.exists() {
+            fs::remove_file(tmp)?;
+        } else {
+            fs::rename(tmp, &path)?;
+        }
+        let mut index = self.index.lock().unwrap();
+        index.insert(source.to_owned(), hash);
+        self.evict(&mut index)?;
+        self.write_index(&index)
+    }
+
+    fn write_index(&self, index: &HashMap<String, String>) -> Result<(), anyhow::Error> {
+        let content = index
+            .iter()
+            .map(|(source, hash)| format!("{hash}\t{source}\n"))
+            .collect::<String>();
+        write_atomic(&self.dir.join(INDEX_FILE), content)?;
+        Ok(())
+    }
+
+    /// Remove least recently used blobs until the cache fits in `max_bytes`.
+    fn evict(&self, index: &mut HashMap<String, String>) -> Result<(), anyhow::Error> {
+        let mut blobs = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(hash) = name.strip_suffix(BLOB_SUFFIX) else {
+                continue;
+            };
+            let meta = entry.metadata()?;
+            let accessed = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            blobs.push((accessed, meta.len(), hash.to_owned()));
+        }
+        let mut total = blobs.iter().map(|(_, size, _)| size).sum::<u64>();
+        blobs.sort();
+        for (_, size, hash) in blobs {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(self.blob_path(&hash))
+                .map_err(|err| anyhow!("failed to evict {hash}: {err}"))?;
+            index.retain(|_, h| *h != hash);
+            total -= size;
+            info!("assignment cache: evicted {hash} ({size} bytes)");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Differences between two assignments: worker set changes, chunk
+//! reassignments per dataset and MPT roots.
+
+use crate::{
+    assignment_cache::AssignmentCache,
+    assignment_source::AssignmentLocation,
+    mpt::{assignment_workers, for_each_chunk, load_assignment, mpt_key, mpt_value},
+    types::{AssignmentDiff, ChunkReassignment, DatasetDiff},
+};
+use eth_trie::{EthTrie, MemoryDB, Trie};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+/// `dataset -> chunk -> sorted workers`
+type ChunkMap = BTreeMap<String, BTreeMap<String, BTreeSet<String>>>;
+
+struct LoadedAssignment {
+    root: Vec<u8>,
+    workers: BTreeSet<String>,
+    chunks: ChunkMap,
+}
+
+async fn load(
+    location: &AssignmentLocation,
+    cache: Option<&AssignmentCache>,
+    max_bytes: u64,
+) -> Result<LoadedAssignment, anyhow::Error> {
+    let (assignment, _) = load_assignment(location, cache, max_bytes).await?;
+    let mut trie = EthTrie::new(Arc::new(MemoryDB::new(true)));
+    let mut chunks = ChunkMap::new();
+    for_each_chunk(&assignment, |dataset_id, chunk_id, workers| {
+        trie.insert(&mpt_key(dataset_id, chunk_id), &mpt_value(&workers))?;
+        chunks
+            .entry(dataset_id.to_owned())
+            .or_default()
+            .insert(chunk_id.to_owned(), workers.into_iter().collect());
+        Ok(())
+    })?;
+    Ok(LoadedAssignment {
+        root: trie.root_hash()?.to_vec(),
+        workers: assignment_workers(&assignment).into_iter().collect(),
+        chunks,
+    })
+}
+
+fn diff_sets(old: &BTreeSet<String>, new: &BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+    (
+        new.difference(old).cloned().collect(),
+        old.difference(new).cloned().collect(),
+    )
+}
+
+/// Load both assignments through the `sqd_assignments` reader and compare them.
+pub async fn diff_assignments(
+    old: &AssignmentLocation,
+    new: &AssignmentLocation,
+    cache: Option<&AssignmentCache>,
+    max_bytes: u64,
+) -> Result<AssignmentDiff, anyhow::Error> {
+    let old = load(old, cache, max_bytes).await?;
+    let new = load(new, cache, max_bytes).await?;
+    let (added_workers, removed_workers) = diff_sets(&old.workers, &new.workers);
+
+    let empty = BTreeMap::new();
+    let dataset_ids = old
+        .chunks
+        .keys()
+        .chain(new.chunks.keys())
+        .collect::<BTreeSet<_>>();
+    let mut datasets = Vec::new();
+    for dataset in dataset_ids {
+        let old_chunks = old.chunks.get(dataset).unwrap_or(&empty);
+        let new_chunks = new.chunks.get(dataset).unwrap_or(&empty);
+        let added_chunks = new_chunks
+            .keys()
+            .filter(|chunk| !old_chunks.contains_key(*chunk))
+            .cloned()
+            .collect::<Vec<_>>();
+        let removed_chunks = old_chunks
+            .keys()
+            .filter(|chunk| !new_chunks.contains_key(*chunk))
+            .cloned()
+            .collect::<Vec<_>>();
+        let reassigned = old_chunks
+            .iter()
+            .filter_map(|(chunk, old_workers)| {
+                let new_workers = new_chunks.get(chunk)?;
+                if old_workers == new_workers {
+                    return None;
+                }
+                let (added_workers, removed_workers) = diff_sets(old_workers, new_workers);
+                Some(ChunkReassignment {
+                    chunk: chunk.clone(),
+                    added_workers,
+                    removed_workers,
+                })
+            })
+            .collect::<Vec<_>>();
+        if added_chunks.is_empty() && removed_chunks.is_empty() && reassigned.is_empty() {
+            continue;
+        }
+        datasets.push(DatasetDiff {
+            dataset: dataset.clone(),
+            added_chunks,
+            removed_chunks,
+            reassigned,
+        });
+    }
+
+    Ok(AssignmentDiff {
+        old_root: old.root,
+        new_root: new.root,
+        added_workers,
+        removed_workers,
+        datasets,
+    })
+}
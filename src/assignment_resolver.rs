@@ -0,0 +1,248 @@
+//! Cached, batched resolution of assignment ids through
+//! `CommitmentHolder.get_id_by_timestamp`.
+//!
+//! Assignments are valid over contiguous time ranges, so once two probed
+//! timestamps return the same id every timestamp between them is known to map
+//! to that id as well.  Unknown timestamps are resolved in Multicall batches.
+
+use crate::{
+    contracts::CommitmentHolder,
+    rpc::RpcPool,
+    types::{AssignmentCandidate, AssignmentMatch, QueryExecutedRow},
+};
+use alloy::{
+    primitives::{Address, U256},
+    providers::Provider,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{info, warn};
+
+/// Maximum number of calls aggregated into one Multicall.
+const MULTICALL_BATCH: usize = 200;
+/// Maximum number of probed timestamps kept; the oldest are dropped first.
+const MAX_PROBES: usize = 100_000;
+/// Results for timestamps newer than this (seconds before now) are not
+/// cached, as a later commitment may still take over that range.
+const FRESHNESS_MARGIN_SEC: u64 = 3600;
+
+/// Assignment ids of already probed timestamps.
+#[derive(Default)]
+struct ProbeCache {
+    /// probed timestamp (seconds) -> assignment id ("" if none)
+    probes: BTreeMap<u64, String>,
+}
+
+impl ProbeCache {
+    fn get(&self, ts: u64) -> Option<String> {
+        if let Some(id) = self.probes.get(&ts) {
+            return Some(id.clone());
+        }
+        let (_, before) = self.probes.range(..ts).next_back()?;
+        let (_, after) = self.probes.range(ts..).next()?;
+        (before == after && !before.is_empty()).then(|| before.clone())
+    }
+
+    /// Remember the id probed for `ts`, unless `ts` is too recent at `now`.
+    fn insert(&mut self, ts: u64, id: &str, now: u64) {
+        if ts + FRESHNESS_MARGIN_SEC < now {
+            self.probes.insert(ts, id.to_owned());
+        }
+    }
+
+    /// Drop the oldest probes beyond `MAX_PROBES`.
+    fn trim(&mut self) {
+        while self.probes.len() > MAX_PROBES {
+            self.probes.pop_first();
+        }
+    }
+}
+
+pub struct AssignmentIdResolver {
+    rpc: Arc<RpcPool>,
+    commiter_address: Address,
+    probes: ProbeCache,
+}
+
+impl AssignmentIdResolver {
+    pub fn new(rpc: Arc<RpcPool>, commiter_address: Address) -> Self {
+        AssignmentIdResolver {
+            rpc,
+            commiter_address,
+            probes: ProbeCache::default(),
+        }
+    }
+
+    /// Assignment id for each of `timestamps` (seconds); empty if the contract
+    /// knows no assignment for it.
+    pub async fn resolve(
+        &mut self,
+        timestamps: &[u64],
+    ) -> Result<HashMap<u64, String>, anyhow::Error> {
+        let mut resolved = HashMap::new();
+        let mut missing = BTreeSet::new();
+        for ts in timestamps {
+            match self.probes.get(*ts) {
+                Some(id) => {
+                    resolved.insert(*ts, id);
+                }
+                None => {
+                    missing.insert(*ts);
+                }
+            }
+        }
+        if missing.is_empty() {
+            return Ok(resolved);
+        }
+        info!(
+            "Resolving {} assignment id(s), {} served from cache",
+            missing.len(),
+            resolved.len()
+        );
+
+        let commiter_address = self.commiter_address;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let missing = missing.into_iter().collect::<Vec<_>>();
+        for batch in missing.chunks(MULTICALL_BATCH) {
+            let ids = self
+                .rpc
+                .call(|provider| async move {
+                    let commiter = CommitmentHolder::new(commiter_address, provider.clone());
+                    let mut multicall = provider
+                        .multicall()
+                        .dynamic::<CommitmentHolder::get_id_by_timestampCall>();
+                    for ts in batch {
+                        multicall =
+                            multicall.add_dynamic(commiter.get_id_by_timestamp(U256::from(*ts)));
+                    }
+                    match multicall.aggregate().await {
+                        Ok(ids) => Ok(ids),
+                        Err(err) => {
+                            warn!("Multicall failed ({err:?}), falling back to sequential calls");
+                            let mut ids = Vec::with_capacity(batch.len());
+                            for ts in batch {
+                                ids.push(
+                                    commiter.get_id_by_timestamp(U256::from(*ts)).call().await?,
+                                );
+                            }
+                            Ok(ids)
+                        }
+                    }
+                })
+                .await?;
+            for (ts, id) in batch.iter().zip(ids) {
+                self.probes.insert(*ts, &id, now);
+                resolved.insert(*ts, id);
+            }
+        }
+        self.probes.trim();
+        Ok(resolved)
+    }
+
+    /// For every sibling, the assignment active at its client timestamp and the
+    /// distinct neighbouring assignments `boundary_window_sec` before and after
+    /// it.  Candidates are ordered by likelihood: the assignment at the
+    /// timestamp first, then the previous and the next one.
+    pub async fn candidates(
+        &mut self,
+        sibling_queries: &[QueryExecutedRow],
+        boundary_window_sec: u64,
+    ) -> Result<HashMap<String, Vec<AssignmentCandidate>>, anyhow::Error> {
+        let probes_of = |row: &QueryExecutedRow| {
+            let ts = row.client_timestamp / 1000;
+            let mut probes = vec![(ts, AssignmentMatch::AtTimestamp)];
+            if boundary_window_sec > 0 {
+                probes.push((ts.saturating_sub(boundary_window_sec), AssignmentMatch::Previous));
+                probes.push((ts + boundary_window_sec, AssignmentMatch::Next));
+            }
+            probes
+        };
+        let timestamps = sibling_queries
+            .iter()
+            .flat_map(|row| probes_of(row).into_iter().map(|(ts, _)| ts))
+            .collect::<Vec<_>>();
+        let ids = self.resolve(&timestamps).await?;
+
+        let mut candidates_map = HashMap::new();
+        for row in sibling_queries {
+            let mut candidates = Vec::<AssignmentCandidate>::new();
+            for (probe_ts, kind) in probes_of(row) {
+                let Some(id) = ids.get(&probe_ts) else {
+                    continue;
+                };
+                if !id.is_empty() && !candidates.iter().any(|c| &c.assignment_id == id) {
+                    candidates.push(AssignmentCandidate {
+                        assignment_id: id.clone(),
+                        probe_timestamp_sec: probe_ts,
+                        kind,
+                    });
+                }
+            }
+            if !candidates.is_empty() {
+                candidates_map.insert(row.query_id.clone(), candidates);
+            }
+        }
+        Ok(candidates_map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn probes_between_equal_ids_are_served_from_cache() {
+        let mut cache = ProbeCache::default();
+        cache.insert(1000, "a", NOW);
+        cache.insert(2000, "a", NOW);
+        cache.insert(3000, "b", NOW);
+        cache.insert(4000, "", NOW);
+        cache.insert(5000, "", NOW);
+
+        assert_eq!(cache.get(1000).as_deref(), Some("a"));
+        assert_eq!(cache.get(1500).as_deref(), Some("a"));
+        // The switch from `a` to `b` lies somewhere in between.
+        assert_eq!(cache.get(2500), None);
+        assert_eq!(cache.get(3000).as_deref(), Some("b"));
+        // A probed gap is known, but not extended to its neighbours.
+        assert_eq!(cache.get(4000).as_deref(), Some(""));
+        assert_eq!(cache.get(4500), None);
+        // Nothing is known outside the probed range.
+        assert_eq!(cache.get(999), None);
+        assert_eq!(cache.get(5001), None);
+    }
+
+    #[test]
+    fn fresh_probes_are_not_cached() {
+        let mut cache = ProbeCache::default();
+        let fresh = NOW - FRESHNESS_MARGIN_SEC;
+        cache.insert(fresh, "a", NOW);
+        assert_eq!(cache.get(fresh), None);
+        cache.insert(fresh - 1, "a", NOW);
+        assert_eq!(cache.get(fresh - 1).as_deref(), Some("a"));
+
+        // Once old enough the same timestamp is kept.
+        cache.insert(fresh, "a", NOW + 1);
+        assert_eq!(cache.get(fresh).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn oldest_probes_are_trimmed_first() {
+        let mut cache = ProbeCache::default();
+        for ts in 0..MAX_PROBES as u64 + 10 {
+            cache.insert(ts, "a", NOW);
+        }
+        cache.trim();
+        assert_eq!(cache.probes.len(), MAX_PROBES);
+        assert_eq!(cache.get(5), None);
+        assert_eq!(cache.get(10).as_deref(), Some("a"));
+    }
+}
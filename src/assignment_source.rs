@@ -0,0 +1,287 @@
+//! Where assignment files come from: the public metadata server, a mirror
+//! behind another base URL, or a local directory mirror.
+//!
+//! A local mirror uses the same layout as the metadata server
+//! (`{dir}/{network}/{assignment_id}.fb.1.gz`).  It may additionally contain
+//! `{dir}/{network}/index.tsv` with `assignment_id<TAB>relative path` lines for
+//! files that do not follow the default naming.
+
+use anyhow::anyhow;
+use flate2::write::GzDecoder;
+use futures_util::StreamExt;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+use tokio::sync::mpsc;
+
+const INDEX_FILE: &str = "index.tsv";
+const FILE_SUFFIX: &str = ".fb.1.gz";
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+/// Downloaded chunks buffered ahead of the blocking consumer.
+const CHANNEL_CHUNKS: usize = 16;
+
+/// `Vec<u8>` writer that fails once `limit` bytes would be exceeded.  The
+/// decompressed assignment is held in memory whole, as the flatbuffer reader
+/// needs it in one piece; `limit` is what bounds that memory.
+struct LimitedWriter {
+    buf: Vec<u8>,
+    limit: u64,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if (self.buf.len() + data.len()) as u64 > self.limit {
+            return Err(io::Error::other(format!(
+                "decompressed assignment exceeds {} bytes",
+                self.limit
+            )));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Feed everything `reader` yields to `sink`, chunk by chunk.
+pub(crate) fn copy_chunks(
+    reader: &mut dyn Read,
+    mut sink: impl FnMut(&[u8]) -> Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let mut buf = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        sink(&buf[..n])?;
+    }
+}
+
+/// Blocking reader over chunks sent by an async download; a download error
+/// is returned from `read`.
+struct ChannelReader {
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.pos);
+        buf[..n].copy_from_slice(&self.chunk[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Incremental gzip decoder fed with compressed chunks as they arrive, so the
+/// compressed file never has to be held in memory.
+pub struct AssignmentDecoder {
+    decoder: GzDecoder<LimitedWriter>,
+    compressed_bytes: u64,
+}
+
+/// Decompressed assignment together with load statistics.
+pub struct DecodedAssignment {
+    pub bytes: Vec<u8>,
+    pub compressed_bytes: u64,
+    pub from_cache: bool,
+}
+
+impl AssignmentDecoder {
+    pub fn new(limit: u64) -> Self {
+        AssignmentDecoder {
+            decoder: GzDecoder::new(LimitedWriter {
+                buf: Vec::new(),
+                limit,
+            }),
+            compressed_bytes: 0,
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), anyhow::Error> {
+        self.compressed_bytes += chunk.len() as u64;
+        self.decoder.write_all(chunk)?;
+        Ok(())
+    }
+
+    pub fn finish(self, from_cache: bool) -> Result<DecodedAssignment, anyhow::Error> {
+        let writer = self.decoder.finish()?;
+        Ok(DecodedAssignment {
+            bytes: writer.buf,
+            compressed_bytes: self.compressed_bytes,
+            from_cache,
+        })
+    }
+}
+
+/// A single compressed assignment file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AssignmentLocation {
+    Url(String),
+    File(PathBuf),
+}
+
+impl AssignmentLocation {
+    /// Interpret user input: `http(s)://` URLs are fetched over HTTP,
+    /// anything else is a local path.
+    pub fn parse(value: &str) -> Self {
+        if value.starts_with("http://") || value.starts_with("https://") {
+            AssignmentLocation::Url(value.to_owned())
+        } else {
+            AssignmentLocation::File(PathBuf::from(value))
+        }
+    }
+
+    /// Run `consume` on the blocking pool with a reader over the compressed
+    /// assignment.  Downloads are streamed into the reader as they arrive.
+    pub async fn read_blocking<T: Send + 'static>(
+        &self,
+        consume: impl FnOnce(&mut dyn Read) -> Result<T, anyhow::Error> + Send + 'static,
+    ) -> Result<T, anyhow::Error> {
+        match self {
+            AssignmentLocation::Url(url) => {
+                let response = reqwest::get(url).await?.error_for_status()?;
+                let (tx, rx) = mpsc::channel(CHANNEL_CHUNKS);
+                let consumer = tokio::task::spawn_blocking(move || {
+                    consume(&mut ChannelReader {
+                        rx,
+                        chunk: Vec::new(),
+                        pos: 0,
+                    })
+                });
+                let mut stream = response.bytes_stream();
+                while let Some(chunk) = stream.next().await {
+                    let chunk = chunk.map(|c| c.to_vec()).map_err(io::Error::other);
+                    let failed = chunk.is_err();
+                    // A closed channel means the consumer gave up early.
+                    if tx.send(chunk).await.is_err() || failed {
+                        break;
+                    }
+                }
+                drop(tx);
+                consumer.await?
+            }
+            AssignmentLocation::File(path) => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || consume(&mut File::open(&path)?)).await?
+            }
+        }
+    }
+
+    /// Stream and decompress the assignment without caching it.
+    pub async fn load(&self, limit: u64) -> Result<DecodedAssignment, anyhow::Error> {
+        self.read_blocking(move |reader| {
+            let mut decoder = AssignmentDecoder::new(limit);
+            copy_chunks(reader, |chunk| decoder.feed(chunk))?;
+            decoder.finish(false)
+        })
+        .await
+    }
+}
+
+impl fmt::Display for AssignmentLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssignmentLocation::Url(url) => write!(f, "{url}"),
+            AssignmentLocation::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Resolves `(network, assignment_id)` to an [`AssignmentLocation`].
+#[derive(Debug, Clone)]
+pub enum AssignmentSource {
+    Http { base_url: String },
+    LocalMirror { dir: PathBuf },
+}
+
+impl AssignmentSource {
+    /// `http(s)://` values are base URLs, anything else a mirror directory.
+    pub fn parse(value: &str) -> Self {
+        match AssignmentLocation::parse(value) {
+            AssignmentLocation::Url(url) => AssignmentSource::Http {
+                base_url: url.trim_end_matches('/').to_owned(),
+            },
+            AssignmentLocation::File(dir) => AssignmentSource::LocalMirror { dir },
+        }
+    }
+
+    pub fn locate(
+        &self,
+        network: &str,
+        assignment_id: &str,
+    ) -> Result<AssignmentLocation, anyhow::Error> {
+        match self {
+            AssignmentSource::Http { base_url } => Ok(AssignmentLocation::Url(format!(
+                "{base_url}/{network}/{assignment_id}{FILE_SUFFIX}"
+            ))),
+            AssignmentSource::LocalMirror { dir } => {
+                let network_dir = dir.join(network);
+                let path = match read_index(&network_dir)?.remove(assignment_id) {
+                    Some(relative) => network_dir.join(relative),
+                    None => network_dir.join(format!("{assignment_id}{FILE_SUFFIX}")),
+                };
+                if path.exists() {
+                    Ok(AssignmentLocation::File(path))
+                } else {
+                    Err(anyhow!(
+                        "Assignment {assignment_id} is not in the local mirror ({})",
+                        path.display()
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Assignment ids available for `network`, sorted.  Only local mirrors
+    /// can be listed.
+    pub fn list_ids(&self, network: &str) -> Result<Vec<String>, anyhow::Error> {
+        match self {
+            AssignmentSource::Http { .. } => {
+                Err(anyhow!("Listing assignments is only supported for local mirrors"))
+            }
+            AssignmentSource::LocalMirror { dir } => {
+                let network_dir = dir.join(network);
+                let mut ids = read_index(&network_dir)?.into_keys().collect::<Vec<_>>();
+                for entry in fs::read_dir(&network_dir)? {
+                    let name = entry?.file_name().to_string_lossy().into_owned();
+                    if let Some(id) = name.strip_suffix(FILE_SUFFIX) {
+                        ids.push(id.to_owned());
+                    }
+                }
+                ids.sort();
+                ids.dedup();
+                Ok(ids)
+            }
+        }
+    }
+}
+
+fn read_index(network_dir: &Path) -> Result<HashMap<String, String>, anyhow::Error> {
+    let path = network_dir.join(INDEX_FILE);
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(id, relative)| (id.to_owned(), relative.to_owned()))
+        .collect())
+}
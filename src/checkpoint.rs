@@ -0,0 +1,33 @@
+//! Persisted "last fully processed block" of an on-chain event indexer.
+
+use crate::store::write_atomic;
+use std::{fs, path::PathBuf};
+
+pub struct BlockCheckpoint {
+    path: PathBuf,
+}
+
+impl BlockCheckpoint {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        BlockCheckpoint { path: path.into() }
+    }
+
+    /// The stored block number, or `None` if nothing was saved yet.
+    pub fn load(&self) -> Result<Option<u64>, anyhow::Error> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(&self.path)?.trim().parse()?))
+    }
+
+    /// Atomically replace the stored block number.
+    pub fn save(&self, block: u64) -> Result<(), anyhow::Error> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        write_atomic(&self.path, format!("{block}\n"))?;
+        Ok(())
+    }
+}
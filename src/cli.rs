@@ -0,0 +1,226 @@
+//! One-off command line tools, run instead of the server when a subcommand is
+//! given.
+
+use crate::{
+    admin::{run_manager_action, submit},
+    assignment_diff::diff_assignments,
+    assignment_resolver::AssignmentIdResolver,
+    assignment_source::{AssignmentLocation, AssignmentSource},
+    commitments::{commit_calldata, is_committed, list_commitments, local_commitment},
+    mpt::{for_each_chunk, load_assignment, make_mpt_proof, populate_trie},
+    rpc::RpcPool,
+    types::{Args, AssignmentAction, AssignmentTarget, Command, CommitmentAction},
+};
+use alloy::hex;
+use anyhow::anyhow;
+use eth_trie::{EthTrie, MemoryDB, Trie};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub async fn run_command(command: Command, args: &Args) -> Result<(), anyhow::Error> {
+    match command {
+        Command::Assignment { target, action } => inspect_assignment(&target, action, args).await,
+        Command::AssignmentDiff {
+            old_id,
+            old_location,
+            new_id,
+            new_location,
+        } => {
+            let old = AssignmentTarget {
+                location: old_location,
+                id: old_id,
+            };
+            let new = AssignmentTarget {
+                location: new_location,
+                id: new_id,
+            };
+            print_assignment_diff(&old, &new, args).await
+        }
+        Command::Commitment { dry_run, action } => run_commitment_action(action, dry_run, args).await,
+        Command::Manager { dry_run, action } => run_manager_action(action, dry_run, args).await,
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Resolve `--location` / `--id` to the assignment file to load.
+pub fn resolve_target(
+    target: &AssignmentTarget,
+    args: &Args,
+) -> Result<AssignmentLocation, anyhow::Error> {
+    match (&target.location, &target.id) {
+        (Some(location), _) => Ok(AssignmentLocation::parse(location)),
+        (None, Some(id)) => {
+            AssignmentSource::parse(&args.assignment_source).locate(&args.network, id)
+        }
+        (None, None) => Err(anyhow!("Either --location or --id is required")),
+    }
+}
+
+async fn inspect_assignment(
+    target: &AssignmentTarget,
+    action: AssignmentAction,
+    args: &Args,
+) -> Result<(), anyhow::Error> {
+    let location = resolve_target(target, args)?;
+    let max_bytes = args.assignment_max_decompressed_bytes;
+    match action {
+        AssignmentAction::Root => {
+            let mut trie = EthTrie::new(Arc::new(MemoryDB::new(true)));
+            populate_trie(&location, &mut trie, None, max_bytes).await?;
+            println!("0x{}", hex::encode(trie.root_hash()?));
+        }
+        AssignmentAction::Workers { dataset, chunk } => {
+            let (assignment, _) = load_assignment(&location, None, max_bytes).await?;
+            let mut found = None;
+            for_each_chunk(&assignment, |dataset_id, chunk_id, workers| {
+                if dataset_id == dataset && chunk_id == chunk {
+                    found = Some(workers);
+                }
+                Ok(())
+            })?;
+            let mut workers =
+                found.ok_or(anyhow!("Chunk {dataset}|{chunk} is not in the assignment"))?;
+            workers.sort();
+            for worker in workers {
+                println!("{worker}");
+            }
+        }
+        AssignmentAction::Chunks { worker } => {
+            let (assignment, _) = load_assignment(&location, None, max_bytes).await?;
+            for_each_chunk(&assignment, |dataset_id, chunk_id, workers| {
+                if workers.contains(&worker) {
+                    println!("{dataset_id}|{chunk_id}");
+                }
+                Ok(())
+            })?;
+        }
+        AssignmentAction::Proof {
+            dataset,
+            chunk,
+            worker,
+        } => {
+            let mut trie = EthTrie::new(Arc::new(MemoryDB::new(true)));
+            populate_trie(&location, &mut trie, None, max_bytes).await?;
+            let proof = make_mpt_proof(&mut trie, &dataset, &chunk, &worker)?;
+            println!("root: 0x{}", hex::encode(trie.root_hash()?));
+            for node in proof {
+                println!("0x{}", hex::encode(node));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn print_assignment_diff(
+    old: &AssignmentTarget,
+    new: &AssignmentTarget,
+    args: &Args,
+) -> Result<(), anyhow::Error> {
+    let diff = diff_assignments(
+        &resolve_target(old, args)?,
+        &resolve_target(new, args)?,
+        None,
+        args.assignment_max_decompressed_bytes,
+    )
+    .await?;
+    println!("old root: 0x{}", hex::encode(&diff.old_root));
+    println!("new root: 0x{}", hex::encode(&diff.new_root));
+    for worker in &diff.added_workers {
+        println!("+ worker {worker}");
+    }
+    for worker in &diff.removed_workers {
+        println!("- worker {worker}");
+    }
+    for dataset in &diff.datasets {
+        println!(
+            "dataset {}: {} added, {} removed, {} reassigned chunk(s)",
+            dataset.dataset,
+            dataset.added_chunks.len(),
+            dataset.removed_chunks.len(),
+            dataset.reassigned.len()
+        );
+        for chunk in &dataset.added_chunks {
+            println!("  + {chunk}");
+        }
+        for chunk in &dataset.removed_chunks {
+            println!("  - {chunk}");
+        }
+        for reassignment in &dataset.reassigned {
+            println!(
+                "  ~ {}: +{:?} -{:?}",
+                reassignment.chunk, reassignment.added_workers, reassignment.removed_workers
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn run_commitment_action(
+    action: CommitmentAction,
+    dry_run: bool,
+    args: &Args,
+) -> Result<(), anyhow::Error> {
+    let rpc = Arc::new(RpcPool::from_args(args));
+    match action {
+        CommitmentAction::List { from, to, step } => {
+            let to = to.unwrap_or_else(now_secs);
+            for span in list_commitments(rpc, args.commiter_address, from, to, step).await? {
+                println!("{}\t{}\t{}", span.from_sec, span.to_sec, span.assignment_id);
+            }
+        }
+        CommitmentAction::Show { timestamp } => {
+            let timestamp = timestamp.unwrap_or_else(now_secs);
+            let mut resolver = AssignmentIdResolver::new(Arc::clone(&rpc), args.commiter_address);
+            let id = resolver
+                .resolve(&[timestamp])
+                .await?
+                .remove(&timestamp)
+                .unwrap_or_default();
+            if id.is_empty() {
+                return Err(anyhow!("No commitment at {timestamp}"));
+            }
+            println!("id: {id}");
+            let target = AssignmentTarget {
+                location: None,
+                id: Some(id),
+            };
+            let commitment = local_commitment(
+                &resolve_target(&target, args)?,
+                None,
+                args.assignment_max_decompressed_bytes,
+            )
+            .await?;
+            let confirmed = is_committed(&rpc, args.commiter_address, commitment, timestamp).await?;
+            println!("commitment: {commitment} (confirmed on-chain: {confirmed})");
+        }
+        CommitmentAction::Commit {
+            id,
+            location,
+            timestamp,
+        } => {
+            let target = AssignmentTarget {
+                location,
+                id: Some(id.clone()),
+            };
+            let commitment = local_commitment(
+                &resolve_target(&target, args)?,
+                None,
+                args.assignment_max_decompressed_bytes,
+            )
+            .await?;
+            let timestamp = timestamp.unwrap_or_else(now_secs);
+            println!("commitment: {commitment}");
+            let calldata = commit_calldata(commitment, timestamp, &id);
+            submit(&rpc, args, args.commiter_address, calldata, dry_run).await?;
+        }
+    }
+    Ok(())
+}
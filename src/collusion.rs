@@ -0,0 +1,327 @@
+//! Collusion analysis: accumulates which workers agree with which across
+//! divergent sibling groups and detects small clusters that consistently
+//! side with each other against everyone else.
+
+use crate::types::{Args, CollusionCluster, CollusionReport, QueryExecutedRow};
+use std::collections::{HashMap, HashSet};
+
+/// Thresholds deciding when a group of workers looks like a colluding cluster.
+#[derive(Debug, Clone, Copy)]
+pub struct CollusionThresholds {
+    /// Minimum number of divergent groups a pair must share to be considered.
+    pub min_shared_groups: usize,
+    /// Minimum agreement rate for two workers to be linked into a cluster.
+    pub min_agreement: f64,
+    /// Maximum agreement rate of cluster members with non-members.
+    pub max_outside_agreement: f64,
+}
+
+impl CollusionThresholds {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            min_shared_groups: args.collusion_min_shared_groups,
+            min_agreement: args.collusion_min_agreement,
+            max_outside_agreement: args.collusion_max_outside_agreement,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PairStats {
+    shared: usize,
+    agreed: usize,
+}
+
+/// Worker agreement graph built over time from divergent sibling groups.
+#[derive(Default)]
+pub struct AgreementGraph {
+    pairs: HashMap<(String, String), PairStats>,
+    workers: HashSet<String>,
+    seen_groups: HashSet<String>,
+}
+
+fn pair_key(a: &str, b: &str) -> (String, String) {
+    if a < b {
+        (a.to_owned(), b.to_owned())
+    } else {
+        (b.to_owned(), a.to_owned())
+    }
+}
+
+fn group_key(siblings: &[QueryExecutedRow]) -> Option<String> {
+    let row = siblings.first()?;
+    let hash = row
+        .query_hash
+        .iter()
+        .map(|v| format!("{v:02X}"))
+        .collect::<String>();
+    Some(format!(
+        "{hash}|{}|{}|{:?}|{:?}",
+        row.dataset_id, row.chunk_id, row.from_block, row.to_block
+    ))
+}
+
+/// Workers of the largest output-hash group among `siblings`.
+pub fn majority_workers(siblings: &[QueryExecutedRow]) -> HashSet<String> {
+    let mut map = HashMap::<&[u8], HashSet<String>>::new();
+    for row in siblings {
+        map.entry(&row.output_hash)
+            .or_default()
+            .insert(row.worker_id.clone());
+    }
+    map.into_values()
+        .max_by_key(|workers| workers.len())
+        .unwrap_or_default()
+}
+
+impl AgreementGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one divergent group of siblings.  Groups are identified by
+    /// `(query hash, dataset, chunk, block range)` and only counted once, so
+    /// re-scanning the same window in later iterations does not inflate scores.
+    /// Returns `false` if the group was already recorded.
+    pub fn record_group(&mut self, siblings: &[QueryExecutedRow]) -> bool {
+        let Some(key) = group_key(siblings) else {
+            return false;
+        };
+        if !self.seen_groups.insert(key) {
+            return false;
+        }
+        let mut outputs = HashMap::<&str, &[u8]>::new();
+        for row in siblings {
+            outputs.insert(&row.worker_id, &row.output_hash);
+        }
+        let workers = outputs.keys().copied().collect::<Vec<_>>();
+        for (i, a) in workers.iter().enumerate() {
+            self.workers.insert((*a).to_owned());
+            for b in &workers[i + 1..] {
+                let stats = self.pairs.entry(pair_key(a, b)).or_default();
+                stats.shared += 1;
+                if outputs[a] == outputs[b] {
+                    stats.agreed += 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Clusters of workers that agree with each other far more than with the
+    /// rest of the network.  A cluster must be a strict minority of all
+    /// observed workers; the honest majority naturally forms one big component
+    /// and is never reported.
+    pub fn suspicious_clusters(&self, thresholds: CollusionThresholds) -> Vec<CollusionCluster> {
+        let mut parent = HashMap::<&str, &str>::new();
+        fn find<'a>(parent: &mut HashMap<&'a str, &'a str>, x: &'a str) -> &'a str {
+            let p = *parent.get(x).unwrap_or(&x);
+            if p == x {
+                return x;
+            }
+            let root = find(parent, p);
+            parent.insert(x, root);
+            root
+        }
+
+        for ((a, b), stats) in &self.pairs {
+            if stats.shared >= thresholds.min_shared_groups
+                && stats.agreed as f64 / stats.shared as f64 >= thresholds.min_agreement
+            {
+                let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+                if ra != rb {
+                    parent.insert(ra, rb);
+                }
+            }
+        }
+
+        let mut components = HashMap::<&str, HashSet<&str>>::new();
+        for worker in &self.workers {
+            let root = find(&mut parent, worker);
+            components.entry(root).or_default().insert(worker);
+        }
+
+        let mut clusters = components
+            .into_values()
+            .filter(|members| members.len() >= 2 && members.len() * 2 < self.workers.len())
+            .filter_map(|members| {
+                let (mut inside, mut outside) = (PairStats::default(), PairStats::default());
+                for ((a, b), stats) in &self.pairs {
+                    let target = match (members.contains(a.as_str()), members.contains(b.as_str())) {
+                        (true, true) => &mut inside,
+                        (true, false) | (false, true) => &mut outside,
+                        (false, false) => continue,
+                    };
+                    target.shared += stats.shared;
+                    target.agreed += stats.agreed;
+                }
+                let rate = |s: PairStats| {
+                    if s.shared == 0 {
+                        0.0
+                    } else {
+                        s.agreed as f64 / s.shared as f64
+                    }
+                };
+                let external_agreement = rate(outside);
+                if outside.shared == 0 || external_agreement > thresholds.max_outside_agreement {
+                    return None;
+                }
+                let mut workers = members.into_iter().map(str::to_owned).collect::<Vec<_>>();
+                workers.sort();
+                Some(CollusionCluster {
+                    workers,
+                    shared_groups: inside.shared,
+                    internal_agreement: rate(inside),
+                    external_agreement,
+                })
+            })
+            .collect::<Vec<_>>();
+        clusters.sort_by(|a, b| b.workers.len().cmp(&a.workers.len()));
+        clusters
+    }
+
+    /// If most of the majority of `siblings` belongs to a single suspicious
+    /// cluster, return that cluster: proving against the minority would then
+    /// rely on evidence from likely colluders.
+    pub fn suspicious_majority(
+        &self,
+        siblings: &[QueryExecutedRow],
+        thresholds: CollusionThresholds,
+    ) -> Option<CollusionCluster> {
+        let majority = majority_workers(siblings);
+        self.suspicious_clusters(thresholds)
+            .into_iter()
+            .find(|cluster| {
+                let overlap = cluster
+                    .workers
+                    .iter()
+                    .filter(|w| majority.contains(*w))
+                    .count();
+                overlap * 2 > majority.len()
+            })
+    }
+
+    pub fn report(&self, thresholds: CollusionThresholds) -> CollusionReport {
+        CollusionReport {
+            groups_recorded: self.seen_groups.len(),
+            workers_observed: self.workers.len(),
+            clusters: self.suspicious_clusters(thresholds),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::siblings;
+
+    const THRESHOLDS: CollusionThresholds = CollusionThresholds {
+        min_shared_groups: 3,
+        min_agreement: 0.9,
+        max_outside_agreement: 0.2,
+    };
+
+    const HONEST: [&str; 5] = ["h1", "h2", "h3", "h4", "h5"];
+
+    /// Sibling group `n` with the given `(worker, output)` answers.
+    fn group(n: u8, answers: &[(&str, u8)]) -> Vec<QueryExecutedRow> {
+        let mut rows = siblings(answers);
+        for row in &mut rows {
+            row.query_hash = vec![n; 32];
+        }
+        rows
+    }
+
+    /// Divergent group `n`: the honest workers answer 1, `colluders` answer 2.
+    fn divergent(n: u8, colluders: &[&str]) -> Vec<QueryExecutedRow> {
+        let answers = HONEST
+            .iter()
+            .map(|w| (*w, 1))
+            .chain(colluders.iter().map(|w| (*w, 2)))
+            .collect::<Vec<_>>();
+        group(n, &answers)
+    }
+
+    fn cluster_workers(graph: &AgreementGraph) -> Vec<Vec<String>> {
+        graph
+            .suspicious_clusters(THRESHOLDS)
+            .into_iter()
+            .map(|c| c.workers)
+            .collect()
+    }
+
+    #[test]
+    fn groups_are_recorded_once() {
+        let mut graph = AgreementGraph::new();
+        assert!(graph.record_group(&divergent(0, &["c1"])));
+        assert!(!graph.record_group(&divergent(0, &["c1"])));
+        let report = graph.report(THRESHOLDS);
+        assert_eq!(report.groups_recorded, 1);
+        assert_eq!(report.workers_observed, 6);
+    }
+
+    #[test]
+    fn minority_cluster_is_reported_and_majority_is_not() {
+        let mut graph = AgreementGraph::new();
+        for n in 0..3 {
+            graph.record_group(&divergent(n, &["c1", "c2"]));
+        }
+        let clusters = graph.suspicious_clusters(THRESHOLDS);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].workers, ["c1", "c2"]);
+        assert_eq!(clusters[0].shared_groups, 3);
+        assert_eq!(clusters[0].internal_agreement, 1.0);
+        assert_eq!(clusters[0].external_agreement, 0.0);
+    }
+
+    #[test]
+    fn too_few_shared_groups_link_nobody() {
+        let mut graph = AgreementGraph::new();
+        for n in 0..2 {
+            graph.record_group(&divergent(n, &["c1", "c2"]));
+        }
+        assert!(cluster_workers(&graph).is_empty());
+    }
+
+    #[test]
+    fn links_are_transitive() {
+        // c1 and c3 never answer the same query but both side with c2.
+        let mut graph = AgreementGraph::new();
+        for n in 0..3 {
+            graph.record_group(&divergent(n, &["c1", "c2"]));
+            graph.record_group(&divergent(n + 10, &["c2", "c3"]));
+        }
+        assert_eq!(cluster_workers(&graph), [["c1", "c2", "c3"]]);
+    }
+
+    #[test]
+    fn cluster_agreeing_with_outsiders_is_not_reported() {
+        let mut graph = AgreementGraph::new();
+        for n in 0..3 {
+            graph.record_group(&divergent(n, &["c1", "c2"]));
+        }
+        // c1 and c2 side with h1..h4 against h5 in most other groups.
+        for n in 3..20 {
+            graph.record_group(&group(
+                n,
+                &[("h1", 1), ("h2", 1), ("h3", 1), ("h4", 1), ("h5", 2), ("c1", 1), ("c2", 1)],
+            ));
+        }
+        assert!(cluster_workers(&graph).is_empty());
+    }
+
+    #[test]
+    fn suspicious_majority_is_detected() {
+        let mut graph = AgreementGraph::new();
+        for n in 0..3 {
+            graph.record_group(&divergent(n, &["c1", "c2"]));
+        }
+        // c1, c2 and h1 outvote h2.
+        let captured = siblings(&[("c1", 2), ("c2", 2), ("h1", 2), ("h2", 1)]);
+        let cluster = graph.suspicious_majority(&captured, THRESHOLDS).unwrap();
+        assert_eq!(cluster.workers, ["c1", "c2"]);
+        // An honest majority that the colluders merely belong to is fine.
+        let honest = siblings(&[("h1", 1), ("h2", 1), ("h3", 1), ("c1", 1), ("c2", 2)]);
+        assert!(graph.suspicious_majority(&honest, THRESHOLDS).is_none());
+    }
+}
@@ -0,0 +1,147 @@
+//! CommitmentHolder inspection and commit helpers.
+//!
+//! The contract emits no events and has no getter for the stored commitment,
+//! only `get_id_by_timestamp` and `check_timestamp`.  Commitments are
+//! therefore listed by probing ids over time and locating the switches by
+//! bisection, and a commitment is shown as the root computed locally from the
+//! assignment together with whether the contract confirms it.
+
+use crate::{
+    assignment_cache::AssignmentCache,
+    assignment_resolver::AssignmentIdResolver,
+    assignment_source::AssignmentLocation,
+    contracts::CommitmentHolder,
+    mpt::populate_trie,
+    rpc::RpcPool,
+};
+use alloy::{
+    primitives::{Address, FixedBytes, U256},
+    sol_types::SolCall,
+};
+use anyhow::anyhow;
+use eth_trie::{EthTrie, MemoryDB, Trie};
+use std::sync::Arc;
+
+/// An assignment id together with the time range (seconds, inclusive) over
+/// which the contract returns it.
+#[derive(Debug, Clone)]
+pub struct CommitmentSpan {
+    pub assignment_id: String,
+    pub from_sec: u64,
+    pub to_sec: u64,
+}
+
+/// First second in `(lo, hi]` whose id differs from the id at `lo`.
+async fn find_switch(
+    resolver: &mut AssignmentIdResolver,
+    mut lo: u64,
+    mut hi: u64,
+) -> Result<u64, anyhow::Error> {
+    let lo_id = resolver.resolve(&[lo]).await?.remove(&lo).unwrap_or_default();
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        let mid_id = resolver.resolve(&[mid]).await?.remove(&mid).unwrap_or_default();
+        if mid_id == lo_id {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(hi)
+}
+
+/// Commitments active between `from_sec` and `to_sec`.  Ids are probed every
+/// `step_sec` seconds, so an assignment active for less than that may be
+/// missed.  Gaps without a commitment are omitted.
+pub async fn list_commitments(
+    rpc: Arc<RpcPool>,
+    commiter_address: Address,
+    from_sec: u64,
+    to_sec: u64,
+    step_sec: u64,
+) -> Result<Vec<CommitmentSpan>, anyhow::Error> {
+    if from_sec > to_sec {
+        return Err(anyhow!("Empty time range {from_sec}..{to_sec}"));
+    }
+    let mut resolver = AssignmentIdResolver::new(rpc, commiter_address);
+    let mut probes = (from_sec..to_sec).step_by(step_sec.max(1) as usize).collect::<Vec<_>>();
+    probes.push(to_sec);
+    let ids = resolver.resolve(&probes).await?;
+
+    let mut spans = Vec::<CommitmentSpan>::new();
+    let mut current = (from_sec, ids[&from_sec].clone());
+    for pair in probes.windows(2) {
+        let (prev, next) = (pair[0], pair[1]);
+        if ids[&next] == ids[&prev] {
+            continue;
+        }
+        let mut lo = prev;
+        loop {
+            let switch = find_switch(&mut resolver, lo, next).await?;
+            let id = resolver.resolve(&[switch]).await?.remove(&switch).unwrap_or_default();
+            if !current.1.is_empty() {
+                spans.push(CommitmentSpan {
+                    assignment_id: current.1.clone(),
+                    from_sec: current.0,
+                    to_sec: switch - 1,
+                });
+            }
+            current = (switch, id);
+            // Several switches may lie between two probes.
+            if current.1 == ids[&next] {
+                break;
+            }
+            lo = switch;
+        }
+    }
+    if !current.1.is_empty() {
+        spans.push(CommitmentSpan {
+            assignment_id: current.1,
+            from_sec: current.0,
+            to_sec,
+        });
+    }
+    Ok(spans)
+}
+
+/// MPT root of the assignment at `location`, i.e. the commitment to store.
+pub async fn local_commitment(
+    location: &AssignmentLocation,
+    cache: Option<&AssignmentCache>,
+    max_bytes: u64,
+) -> Result<FixedBytes<32>, anyhow::Error> {
+    let mut trie = EthTrie::new(Arc::new(MemoryDB::new(true)));
+    populate_trie(location, &mut trie, cache, max_bytes).await?;
+    Ok(FixedBytes::from_slice(trie.root_hash()?.as_ref()))
+}
+
+/// Whether the contract confirms `commitment` for `timestamp_sec`.
+pub async fn is_committed(
+    rpc: &RpcPool,
+    commiter_address: Address,
+    commitment: FixedBytes<32>,
+    timestamp_sec: u64,
+) -> Result<bool, anyhow::Error> {
+    rpc.call(|provider| async move {
+        let commiter = CommitmentHolder::new(commiter_address, provider);
+        Ok(commiter
+            .check_timestamp(commitment, U256::from(timestamp_sec))
+            .call()
+            .await?)
+    })
+    .await
+}
+
+/// Calldata of `CommitmentHolder.commit`.
+pub fn commit_calldata(
+    commitment: FixedBytes<32>,
+    timestamp_sec: u64,
+    assignment_id: &str,
+) -> Vec<u8> {
+    CommitmentHolder::commitCall {
+        commitment,
+        timestamp: U256::from(timestamp_sec),
+        assignment_id: assignment_id.to_owned(),
+    }
+    .abi_encode()
+}
@@ -1,14 +1,19 @@
-//! On-chain contract interactions: ABI bindings, `get_assignment_id_map`,
-//! `filter_eligible_queries`, and `post_proof`.
+//! On-chain contract interactions: ABI bindings, `filter_eligible_queries`,
+//! `check_assignment_root`, and `post_proof`.  Timestamp to assignment id
+//! lookups go through [`crate::assignment_resolver::AssignmentIdResolver`].
 
-use crate::types::QueryExecutedRow;
+use crate::{
+    rpc::RpcPool,
+    submission::{SubmissionReceipt, Submitter},
+    types::{AssignmentCandidate, QueryExecutedRow},
+};
 use alloy::{
-    primitives::{Address, Uint},
-    providers::{ProviderBuilder, WsConnect},
-    signers::local::PrivateKeySigner,
+    hex,
+    primitives::{Address, FixedBytes, Uint},
     sol,
+    sol_types::SolCall,
 };
-use std::{cmp::Ordering, collections::HashMap};
+use std::{cmp::Ordering, collections::HashMap, fmt};
 use tracing::info;
 
 // ---------------------------------------------------------------------------
@@ -33,25 +38,89 @@ sol!(
 // Contract helpers
 // ---------------------------------------------------------------------------
 
-pub async fn get_assignment_id_map(
-    sibling_queries: &Vec<QueryExecutedRow>,
-    rpc_url: &str,
+/// The most likely assignment id of every query, i.e. the first candidate.
+pub fn primary_assignment_map(
+    candidates: &HashMap<String, Vec<AssignmentCandidate>>,
+) -> HashMap<String, String> {
+    candidates
+        .iter()
+        .filter_map(|(query_id, c)| Some((query_id.clone(), c.first()?.assignment_id.clone())))
+        .collect()
+}
+
+/// The MPT root built locally for an assignment is not the one committed
+/// on-chain for the given timestamp.
+#[derive(Debug, Clone)]
+pub struct CommitmentMismatch {
+    pub assignment_id: String,
+    pub local_root: Vec<u8>,
+    pub timestamp_sec: u64,
+}
+
+impl fmt::Display for CommitmentMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "local MPT root 0x{} of assignment {} is not committed on-chain for timestamp {}",
+            hex::encode(&self.local_root),
+            self.assignment_id,
+            self.timestamp_sec
+        )
+    }
+}
+
+impl std::error::Error for CommitmentMismatch {}
+
+/// Results of `check_timestamp` calls, kept for one discovery iteration so
+/// that evidence rows sharing an assignment and a timestamp cost one RPC.
+#[derive(Default)]
+pub struct RootCheckCache {
+    results: HashMap<(String, FixedBytes<32>, u64), bool>,
+}
+
+impl RootCheckCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Check via `CommitmentHolder.check_timestamp` that `local_root` is the
+/// commitment valid at `timestamp_sec`.  Returns a [`CommitmentMismatch`]
+/// error otherwise.  Answers are memoised in `cache`.
+pub async fn check_assignment_root(
+    rpc: &RpcPool,
     commiter_address: Address,
-) -> Result<HashMap<String, String>, anyhow::Error> {
-    let ws = WsConnect::new(rpc_url);
-    let provider = ProviderBuilder::new().connect_ws(ws.clone()).await?;
-    let mut assignment_id_map = HashMap::<String, String>::new();
-    let commiter = CommitmentHolder::new(commiter_address, provider.clone());
-    for row in sibling_queries {
-        let ts = row.client_timestamp / 1000;
-        let ts: [u64; 4] = [ts, 0, 0, 0];
-        let ts = Uint::<256, 4>::from_limbs(ts);
-        let res = commiter.get_id_by_timestamp(ts).call().await?;
-        if !res.is_empty() {
-            assignment_id_map.insert(row.query_id.clone(), res);
+    assignment_id: &str,
+    local_root: &[u8],
+    timestamp_sec: u64,
+    cache: &mut RootCheckCache,
+) -> Result<(), anyhow::Error> {
+    let root = FixedBytes::<32>::try_from(local_root)?;
+    let key = (assignment_id.to_owned(), root, timestamp_sec);
+    let committed = match cache.results.get(&key) {
+        Some(committed) => *committed,
+        None => {
+            let ts = Uint::<256, 4>::from_limbs([timestamp_sec, 0, 0, 0]);
+            let committed = rpc
+                .call(|provider| async move {
+                    let commiter = CommitmentHolder::new(commiter_address, provider);
+                    Ok(commiter.check_timestamp(root, ts).call().await?)
+                })
+                .await?;
+            cache.results.insert(key, committed);
+            committed
+        }
+    };
+    if committed {
+        Ok(())
+    } else {
+        Err(CommitmentMismatch {
+            assignment_id: assignment_id.to_owned(),
+            local_root: local_root.to_vec(),
+            timestamp_sec,
         }
+        .into())
     }
-    Ok(assignment_id_map)
 }
 
 pub fn filter_eligible_queries(
@@ -77,29 +146,20 @@ pub fn filter_eligible_queries(
     eligible_queries
 }
 
+/// Submit a proof to `verifyAndEmit` with managed fees and wait until the
+/// transaction is confirmed.
 pub async fn post_proof(
     proof_bytes: Vec<u8>,
     public_values: Vec<u8>,
-    rpc_url: &str,
-    signer: PrivateKeySigner,
+    submitter: &Submitter,
     manager_address: Address,
     config_name: &str,
-) -> Result<Vec<u8>, anyhow::Error> {
-    let ws = WsConnect::new(rpc_url);
-    let wallet_provider = ProviderBuilder::new().wallet(signer).connect_ws(ws).await?;
-    let prover = ProvingManager::new(manager_address, wallet_provider.clone());
-    let pending = prover
-        .verifyAndEmit(
-            config_name.to_owned(),
-            public_values.into(),
-            proof_bytes.into(),
-        )
-        .send()
-        .await?;
-    let res = pending
-        .with_required_confirmations(2)
-        .with_timeout(Some(std::time::Duration::from_secs(60)))
-        .watch()
-        .await?;
-    Ok(res.to_vec())
+) -> Result<SubmissionReceipt, anyhow::Error> {
+    let calldata = ProvingManager::verifyAndEmitCall {
+        config_name: config_name.to_owned(),
+        publicValues: public_values.into(),
+        proofBytes: proof_bytes.into(),
+    }
+    .abi_encode();
+    submitter.send(manager_address, calldata).await
 }
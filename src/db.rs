@@ -1,9 +1,12 @@
 //! ClickHouse query functions.
 
-use crate::types::{HashRow, InvestigationRow, QueryExecutedRow, QueryIdRow, SignatureRow};
+use crate::types::{
+    HashRow, InvestigationRow, OddQuery, QueryExecutedRow, QueryIdRow, QueryTimestampRow,
+    QueryTimingRow, QuorumRules, QuorumTiePolicy, SignatureRow, WorkerFailuresRow,
+};
 use anyhow::anyhow;
 use clickhouse::Client;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tracing::{debug, info};
 
 // ---------------------------------------------------------------------------
@@ -155,62 +158,107 @@ pub async fn get_siblings_queries_by_investigate_row(
     Ok(sibling_queries)
 }
 
-/// Return a stable identification of the odd-one-out query ids from a set of
-/// siblings that produced different output hashes.
+/// Return the odd-one-out queries from a set of siblings that produced
+/// different output hashes, provided the majority satisfies `rules`.
+///
+/// The confidence of an accusation is `majority / (majority + accused)`, the
+/// distinct workers of the majority group over those of the majority and the
+/// accused group together, damped by the absolute size of the majority
+/// (`1 - 1 / (majority + 1)`), so a 3-vs-2 split scores lower than a 9-vs-1.
 pub fn find_odds_in_siblings(
     siblings: &Vec<QueryExecutedRow>,
-) -> Result<Vec<String>, anyhow::Error> {
-    let mut map = HashMap::<String, Vec<String>>::default();
+    rules: &QuorumRules,
+) -> Result<Vec<OddQuery>, anyhow::Error> {
+    let mut map = HashMap::<&[u8], Vec<&QueryExecutedRow>>::default();
     for sibling in siblings {
-        let key = sibling
-            .output_hash
-            .iter()
-            .map(|v| format!("{v:02X}"))
-            .collect::<Vec<_>>()
-            .join("");
-        let value = map.entry(key).or_insert(vec![]);
-        (*value).push(sibling.query_id.clone());
+        map.entry(&sibling.output_hash).or_default().push(sibling);
     }
+    let distinct_workers =
+        |rows: &[&QueryExecutedRow]| rows.iter().map(|r| &r.worker_id).collect::<HashSet<_>>().len();
+
     let max_num = map
-        .iter()
-        .map(|(_, v)| v.len())
+        .values()
+        .map(|v| distinct_workers(v))
         .max()
         .ok_or(anyhow!("Empty input for odds finder"))?;
-    let res = map
+    let total_workers = distinct_workers(&siblings.iter().collect::<Vec<_>>());
+    let tied = map.values().filter(|v| distinct_workers(v) == max_num).count();
+
+    if total_workers < rules.min_group_size {
+        info!(
+            "Quorum: only {total_workers} distinct worker(s) among siblings, need {}",
+            rules.min_group_size
+        );
+        return Ok(vec![]);
+    }
+    if tied > 1 && rules.tie_policy == QuorumTiePolicy::Skip {
+        info!("Quorum: {tied} output hashes tie with {max_num} worker(s), skipping");
+        return Ok(vec![]);
+    }
+    if max_num < rules.min_majority_workers {
+        info!(
+            "Quorum: majority has {max_num} worker(s), need {}",
+            rules.min_majority_workers
+        );
+        return Ok(vec![]);
+    }
+    let majority_ratio = max_num as f64 / total_workers as f64;
+    if majority_ratio < rules.min_majority_ratio {
+        info!(
+            "Quorum: majority ratio {majority_ratio:.2} is below {:.2}",
+            rules.min_majority_ratio
+        );
+        return Ok(vec![]);
+    }
+
+    let size_factor = 1.0 - 1.0 / (max_num as f64 + 1.0);
+    let mut res = map
         .values()
-        .filter(|v| v.len() < max_num)
-        .cloned()
-        .collect::<Vec<_>>()
-        .concat();
+        .filter(|v| distinct_workers(v) < max_num)
+        .flat_map(|group| {
+            let group_workers = distinct_workers(group);
+            let confidence = max_num as f64 / (max_num + group_workers) as f64 * size_factor;
+            group.iter().map(move |row| OddQuery {
+                query_id: row.query_id.clone(),
+                worker_id: row.worker_id.clone(),
+                confidence,
+            })
+        })
+        .collect::<Vec<_>>();
+    res.sort_by(|a, b| a.query_id.cmp(&b.query_id));
     Ok(res)
 }
 
 /// Look up a `query_id` in ClickHouse by `(worker_id, client_timestamp_ms)`.
-pub async fn get_query_id_by_worker_and_ts(
+/// Queries executed by any of `worker_ids` whose client timestamp lies in
+/// `[from_ms, to_ms]`.  `slack_sec` widens the `worker_timestamp` prefilter
+/// around that range, as the worker clock is not the client clock.
+pub async fn get_queries_by_workers_and_ts(
     client: &Client,
-    worker_id: &str,
-    ts_ms: u64,
-) -> Result<Option<String>, anyhow::Error> {
-    // worker_timestamp in worker_query_logs is in seconds; the event timestamp is in milliseconds.
-    let ts_sec = (ts_ms as f64 / 1000.0) as f64;
-    let mut cursor = client
+    worker_ids: &[String],
+    from_ms: u64,
+    to_ms: u64,
+    slack_sec: u64,
+) -> Result<Vec<QueryTimestampRow>, anyhow::Error> {
+    let range_start_sec = (from_ms / 1000).saturating_sub(slack_sec) as u32;
+    let range_end_sec = (to_ms / 1000 + 1 + slack_sec) as u32;
+    Ok(client
         .query(
-            "SELECT any(query_id) as query_id
+            "SELECT query_id, worker_id, toUnixTimestamp64Milli(client_timestamp) AS client_timestamp_ms
              FROM mainnet.worker_query_logs
-             WHERE client_timestamp = ?
-               AND worker_id = ?
-             HAVING count() > 0
+             WHERE worker_timestamp > ?
+               AND worker_timestamp < ?
+               AND worker_id IN ?
+               AND toUnixTimestamp64Milli(client_timestamp) BETWEEN ? AND ?
             ",
         )
-        .bind(format!("{:0.3}", ts_sec))
-        .bind(worker_id)
-        .fetch::<QueryIdRow>()?;
-
-    if let Some(row) = cursor.next().await? {
-        Ok(Some(row.query_id))
-    } else {
-        Ok(None)
-    }
+        .bind(range_start_sec)
+        .bind(range_end_sec)
+        .bind(worker_ids)
+        .bind(from_ms as i64)
+        .bind(to_ms as i64)
+        .fetch_all::<QueryTimestampRow>()
+        .await?)
 }
 
 // ---------------------------------------------------------------------------
@@ -315,3 +363,187 @@ pub async fn get_signatures(
         .map(|row| (row.query_id, (row.result_hash, row.worker_signature)))
         .collect::<HashMap<String, (Vec<u8>, Vec<u8>)>>())
 }
+
+// ---------------------------------------------------------------------------
+// Response-time lookup (used by the latency detector)
+// ---------------------------------------------------------------------------
+
+pub async fn get_query_timings(
+    client: &Client,
+    range_start_sec: u32,
+    range_end_sec: u32,
+) -> Result<Vec<QueryTimingRow>, anyhow::Error> {
+    client
+        .query(
+            "select
+                p.query_id as query_id,
+                p.worker_id as worker_id,
+                hex(w.query_hash) as hash,
+                w.dataset as dataset,
+                w.chunk_id as chunk_id,
+                p.total_time as total_time,
+                p.collector_timestamp as collector_timestamp
+            from portal_logs as p
+            inner join mainnet.worker_query_logs as w on p.query_id = w.query_id
+            where
+                p.collector_timestamp > ? and
+                p.collector_timestamp < ? and
+                w.worker_timestamp > ? and
+                w.worker_timestamp < ? and
+                w.result == 'ok'",
+        )
+        .bind(range_start_sec)
+        .bind(range_end_sec)
+        .bind(range_start_sec)
+        .bind(range_end_sec)
+        .fetch_all::<QueryTimingRow>()
+        .await
+        .map_err(|err| anyhow!("{err:?}"))
+}
+
+// ---------------------------------------------------------------------------
+// Availability (used by the reputation store)
+// ---------------------------------------------------------------------------
+
+pub async fn get_worker_failures(
+    client: &Client,
+    range_start_sec: u32,
+    range_end_sec: u32,
+) -> Result<Vec<WorkerFailuresRow>, anyhow::Error> {
+    client
+        .query(
+            "select worker_id, count() as failures
+            from mainnet.worker_query_logs
+            where
+                worker_timestamp > ? and
+                worker_timestamp < ? and
+                result in ('server_error', 'server_overloaded', 'too_many_requests')
+            group by worker_id",
+        )
+        .bind(range_start_sec)
+        .bind(range_end_sec)
+        .fetch_all::<WorkerFailuresRow>()
+        .await
+        .map_err(|err| anyhow!("{err:?}"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::types::QueryResult;
+
+    const RULES: QuorumRules = QuorumRules {
+        min_group_size: 3,
+        min_majority_ratio: 0.5,
+        min_majority_workers: 2,
+        tie_policy: QuorumTiePolicy::Skip,
+    };
+
+    /// One sibling per `(worker, output)` pair; query ids follow the order.
+    pub(crate) fn siblings(answers: &[(&str, u8)]) -> Vec<QueryExecutedRow> {
+        answers
+            .iter()
+            .enumerate()
+            .map(|(i, (worker, output))| QueryExecutedRow {
+                query_id: format!("q{i}"),
+                client_id: "client".to_owned(),
+                worker_id: (*worker).to_owned(),
+                dataset_id: "s3://dataset".to_owned(),
+                from_block: Some(0),
+                to_block: Some(100),
+                chunk_id: "0000000000/0000000000-0000000100-00000000".to_owned(),
+                query: "{}".to_owned(),
+                query_hash: vec![0; 32],
+                result: QueryResult::Ok,
+                output_hash: vec![*output; 32],
+                last_block: Some(100),
+                error_msg: String::new(),
+                client_signature: vec![],
+                client_timestamp: 0,
+                request_id: format!("r{i}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quorum_table() {
+        let cases: &[(&str, &[(&str, u8)], QuorumRules, &[&str])] = &[
+            (
+                "2-vs-2 tie is skipped",
+                &[("a", 1), ("b", 1), ("c", 2), ("d", 2)],
+                RULES,
+                &[],
+            ),
+            (
+                "2-vs-2 tie accuses nobody under minorities_only",
+                &[("a", 1), ("b", 1), ("c", 2), ("d", 2)],
+                QuorumRules { tie_policy: QuorumTiePolicy::MinoritiesOnly, ..RULES },
+                &[],
+            ),
+            (
+                "2-vs-2-vs-1 accuses the single worker under minorities_only",
+                &[("a", 1), ("b", 1), ("c", 2), ("d", 2), ("e", 3)],
+                QuorumRules { tie_policy: QuorumTiePolicy::MinoritiesOnly, ..RULES },
+                &["q4"],
+            ),
+            (
+                "2-vs-2-vs-1 is skipped under skip",
+                &[("a", 1), ("b", 1), ("c", 2), ("d", 2), ("e", 3)],
+                RULES,
+                &[],
+            ),
+            (
+                "3-vs-2 below min_majority_ratio",
+                &[("a", 1), ("b", 1), ("c", 1), ("d", 2), ("e", 2)],
+                QuorumRules { min_majority_ratio: 0.7, ..RULES },
+                &[],
+            ),
+            (
+                "3-vs-2 at min_majority_ratio",
+                &[("a", 1), ("b", 1), ("c", 1), ("d", 2), ("e", 2)],
+                QuorumRules { min_majority_ratio: 0.6, ..RULES },
+                &["q3", "q4"],
+            ),
+            (
+                "a worker answering twice counts once",
+                &[("a", 1), ("a", 1), ("b", 2), ("c", 2)],
+                RULES,
+                &["q0", "q1"],
+            ),
+            (
+                "duplicate answers do not reach min_group_size",
+                &[("a", 1), ("a", 1), ("b", 2), ("c", 2)],
+                QuorumRules { min_group_size: 4, ..RULES },
+                &[],
+            ),
+            (
+                "majority below min_majority_workers",
+                &[("a", 1), ("b", 1), ("c", 2)],
+                QuorumRules { min_majority_workers: 3, ..RULES },
+                &[],
+            ),
+        ];
+        for (name, answers, rules, expected) in cases {
+            let odds = find_odds_in_siblings(&siblings(answers), rules).unwrap();
+            let accused = odds.iter().map(|o| o.query_id.as_str()).collect::<Vec<_>>();
+            assert_eq!(accused, *expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn confidence_follows_documented_formula() {
+        let odds = find_odds_in_siblings(
+            &siblings(&[("a", 1), ("b", 1), ("c", 1), ("d", 2)]),
+            &RULES,
+        )
+        .unwrap();
+        assert_eq!(odds.len(), 1);
+        // 3 / (3 + 1) * (1 - 1 / (3 + 1))
+        assert!((odds[0].confidence - 0.5625).abs() < 1e-12);
+    }
+
+    #[test]
+    fn empty_input_is_an_error() {
+        assert!(find_odds_in_siblings(&vec![], &RULES).is_err());
+    }
+}
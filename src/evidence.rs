@@ -0,0 +1,214 @@
+//! Evidence selection for proof assembly: scores eligible sibling queries and
+//! orders them so that stage 7 downloads as few assignments as possible.
+
+use crate::types::QueryExecutedRow;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+/// Why an eligible row was not picked as evidence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvidenceRejection {
+    NoAssignment,
+    NoSignature,
+    MissingBlockRange,
+    MissingLastBlock,
+    /// Another row of the same worker was preferred.
+    DuplicateWorker(String),
+}
+
+impl fmt::Display for EvidenceRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvidenceRejection::NoAssignment => write!(f, "no assignment id"),
+            EvidenceRejection::NoSignature => write!(f, "no worker signature"),
+            EvidenceRejection::MissingBlockRange => write!(f, "missing block range"),
+            EvidenceRejection::MissingLastBlock => write!(f, "missing last block"),
+            EvidenceRejection::DuplicateWorker(query_id) => {
+                write!(f, "worker already represented by {query_id}")
+            }
+        }
+    }
+}
+
+pub struct RejectedEvidence<'a> {
+    pub row: &'a QueryExecutedRow,
+    pub reason: EvidenceRejection,
+}
+
+/// Outcome of [`select_evidence`].
+pub struct EvidenceSelection<'a> {
+    /// Valid candidates, one per worker, in the order they should be tried.
+    /// The first `count` entries form the preferred set; the rest are
+    /// fallbacks in case building an MPT proof fails for a preferred row.
+    pub candidates: Vec<&'a QueryExecutedRow>,
+    /// Number of distinct assignments the preferred set needs.
+    pub assignments: usize,
+    pub rejected: Vec<RejectedEvidence<'a>>,
+}
+
+/// Rank eligible rows for a proof against `original_query_id`.
+///
+/// Rows without an assignment id, signature, block range or last block are
+/// rejected up-front, as is every row but the freshest one per worker.  The
+/// original query always comes first; the rest are chosen greedily,
+/// preferring rows whose assignment id is already in the set (so the built
+/// trie can be reused) and then the freshest ones.
+pub fn select_evidence<'a>(
+    eligible_queries: &'a [QueryExecutedRow],
+    assignment_id_map: &HashMap<String, String>,
+    signatures: &HashMap<String, (Vec<u8>, Vec<u8>)>,
+    original_query_id: &str,
+    count: usize,
+) -> EvidenceSelection<'a> {
+    let mut rejected = Vec::new();
+    let mut per_worker = HashMap::<&str, &QueryExecutedRow>::new();
+    for row in eligible_queries {
+        let reason = if !assignment_id_map.contains_key(&row.query_id) {
+            Some(EvidenceRejection::NoAssignment)
+        } else if !signatures.contains_key(&row.query_id) {
+            Some(EvidenceRejection::NoSignature)
+        } else if row.from_block.is_none() || row.to_block.is_none() {
+            Some(EvidenceRejection::MissingBlockRange)
+        } else if row.last_block.is_none() {
+            Some(EvidenceRejection::MissingLastBlock)
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            rejected.push(RejectedEvidence { row, reason });
+            continue;
+        }
+        match per_worker.get(row.worker_id.as_str()).copied() {
+            Some(current)
+                if current.query_id == original_query_id
+                    || (row.query_id != original_query_id
+                        && current.client_timestamp >= row.client_timestamp) =>
+            {
+                rejected.push(RejectedEvidence {
+                    row,
+                    reason: EvidenceRejection::DuplicateWorker(current.query_id.clone()),
+                });
+            }
+            Some(current) => {
+                rejected.push(RejectedEvidence {
+                    row: current,
+                    reason: EvidenceRejection::DuplicateWorker(row.query_id.clone()),
+                });
+                per_worker.insert(&row.worker_id, row);
+            }
+            None => {
+                per_worker.insert(&row.worker_id, row);
+            }
+        }
+    }
+
+    let mut remaining = per_worker.into_values().collect::<Vec<_>>();
+    remaining.sort_by(|a, b| b.client_timestamp.cmp(&a.client_timestamp));
+    let mut candidates = Vec::with_capacity(remaining.len());
+    let mut used_assignments = HashSet::<&str>::new();
+    if let Some(pos) = remaining.iter().position(|r| r.query_id == original_query_id) {
+        let row = remaining.remove(pos);
+        used_assignments.insert(&assignment_id_map[&row.query_id]);
+        candidates.push(row);
+    }
+    while candidates.len() < count && !remaining.is_empty() {
+        // `remaining` is sorted by freshness, so the first row with a reusable
+        // assignment is the best one; otherwise take the freshest.
+        let pos = remaining
+            .iter()
+            .position(|r| used_assignments.contains(assignment_id_map[&r.query_id].as_str()))
+            .unwrap_or(0);
+        let row = remaining.remove(pos);
+        used_assignments.insert(&assignment_id_map[&row.query_id]);
+        candidates.push(row);
+    }
+    let assignments = used_assignments.len();
+    candidates.extend(remaining);
+
+    EvidenceSelection {
+        candidates,
+        assignments,
+        rejected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::tests::siblings;
+
+    type Signatures = HashMap<String, (Vec<u8>, Vec<u8>)>;
+
+    /// Rows `q0..` for `(worker, assignment id, client timestamp)`, all signed.
+    fn rows(spec: &[(&str, &str, u64)]) -> (Vec<QueryExecutedRow>, HashMap<String, String>, Signatures) {
+        let mut rows = siblings(&spec.iter().map(|(worker, _, _)| (*worker, 1)).collect::<Vec<_>>());
+        let mut assignments = HashMap::new();
+        let mut signatures = HashMap::new();
+        for (row, (_, assignment_id, ts)) in rows.iter_mut().zip(spec) {
+            row.client_timestamp = *ts;
+            assignments.insert(row.query_id.clone(), (*assignment_id).to_owned());
+            signatures.insert(row.query_id.clone(), (vec![1], vec![2]));
+        }
+        (rows, assignments, signatures)
+    }
+
+    fn ids<'a>(rows: &[&'a QueryExecutedRow]) -> Vec<&'a str> {
+        rows.iter().map(|row| row.query_id.as_str()).collect()
+    }
+
+    #[test]
+    fn every_rejection_reason() {
+        let (mut rows, mut assignments, mut signatures) = rows(&[
+            ("a", "A", 100),
+            ("b", "A", 100),
+            ("c", "A", 100),
+            ("d", "A", 100),
+            ("e", "A", 100),
+            ("a", "A", 500),
+            ("f", "A", 100),
+            ("f", "A", 200),
+        ]);
+        assignments.remove("q1");
+        signatures.remove("q2");
+        rows[3].to_block = None;
+        rows[4].last_block = None;
+        let selection = select_evidence(&rows, &assignments, &signatures, "q0", 5);
+        let rejected = selection
+            .rejected
+            .iter()
+            .map(|r| (r.row.query_id.as_str(), r.reason.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rejected,
+            [
+                ("q1", EvidenceRejection::NoAssignment),
+                ("q2", EvidenceRejection::NoSignature),
+                ("q3", EvidenceRejection::MissingBlockRange),
+                ("q4", EvidenceRejection::MissingLastBlock),
+                // The original query is kept even though q5 is fresher.
+                ("q5", EvidenceRejection::DuplicateWorker("q0".to_owned())),
+                // Otherwise the freshest row of a worker wins.
+                ("q6", EvidenceRejection::DuplicateWorker("q7".to_owned())),
+            ]
+        );
+        assert_eq!(ids(&selection.candidates), ["q0", "q7"]);
+    }
+
+    #[test]
+    fn ranking_reuses_assignments_then_prefers_fresh_rows() {
+        let (rows, assignments, signatures) = rows(&[
+            ("a", "A", 50),
+            ("b", "B", 300),
+            ("c", "A", 100),
+            ("d", "B", 200),
+        ]);
+        let selection = select_evidence(&rows, &assignments, &signatures, "q0", 3);
+        // q2 shares the original's assignment; q1 is the freshest of the
+        // rest and q3 is left as a fallback.
+        assert_eq!(ids(&selection.candidates), ["q0", "q2", "q1", "q3"]);
+        assert_eq!(selection.assignments, 2);
+        assert!(selection.rejected.is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Store of every indexed `FraudFound` event.
+//!
+//! Records are persisted as JSON lines (see [`crate::store`]) on every change,
+//! so that they survive restarts even though the indexer resumes from its
+//! checkpoint and does not re-read old blocks.
+
+use crate::{
+    store::{read_jsonl, write_jsonl},
+    types::{FraudEventRecord, FraudEventStatus},
+};
+use std::{collections::HashMap, path::PathBuf};
+use tracing::error;
+
+pub struct FraudEventStore {
+    path: Option<PathBuf>,
+    /// (block hash, log index) -> record
+    events: HashMap<(String, u64), FraudEventRecord>,
+}
+
+impl FraudEventStore {
+    /// In-memory store without persistence.
+    pub fn new() -> Self {
+        FraudEventStore {
+            path: None,
+            events: HashMap::new(),
+        }
+    }
+
+    /// Load (or create) a store persisted at `path`.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let events = read_jsonl::<FraudEventRecord>(&path)?
+            .into_iter()
+            .map(|record| ((record.block_hash.clone(), record.log_index), record))
+            .collect();
+        Ok(FraudEventStore {
+            path: Some(path),
+            events,
+        })
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut records = self.events.values().collect::<Vec<_>>();
+        records.sort_by_key(|r| (r.block_number, r.log_index));
+        if let Err(err) = write_jsonl(path, records) {
+            error!("fraud events: failed to persist {}: {err:?}", path.display());
+        }
+    }
+
+    /// Insert or replace the record of a log.
+    pub fn upsert(&mut self, record: FraudEventRecord) {
+        self.events
+            .insert((record.block_hash.clone(), record.log_index), record);
+        self.persist();
+    }
+
+    /// Update the status of a known record.  Returns `true` if it existed.
+    pub fn set_status(&mut self, block_hash: &str, log_index: u64, status: FraudEventStatus) -> bool {
+        let Some(record) = self.events.get_mut(&(block_hash.to_owned(), log_index)) else {
+            return false;
+        };
+        if record.status != status {
+            record.status = status;
+            self.persist();
+        }
+        true
+    }
+
+    /// Record the outcome of a `query_id` lookup.  Returns the status of the
+    /// event, or `None` if it is unknown.
+    pub fn set_resolution(
+        &mut self,
+        block_hash: &str,
+        log_index: u64,
+        resolution: Result<String, String>,
+    ) -> Option<FraudEventStatus> {
+        let record = self.events.get_mut(&(block_hash.to_owned(), log_index))?;
+        let status = record.status;
+        match resolution {
+            Ok(query_id) => {
+                record.query_id = Some(query_id);
+                record.resolution_error = None;
+            }
+            Err(error) => record.resolution_error = Some(error),
+        }
+        self.persist();
+        Some(status)
+    }
+
+    /// Events not final yet.
+    pub fn pending(&self) -> Vec<FraudEventRecord> {
+        self.events
+            .values()
+            .filter(|r| r.status == FraudEventStatus::Pending)
+            .cloned()
+            .collect()
+    }
+
+    /// Events still on-chain whose `query_id` is not known yet.
+    pub fn unresolved(&self) -> Vec<FraudEventRecord> {
+        self.events
+            .values()
+            .filter(|r| r.query_id.is_none() && r.status != FraudEventStatus::Reorged)
+            .cloned()
+            .collect()
+    }
+
+    /// Records newest first, optionally restricted to one peer.
+    pub fn list(&self, peer_id: Option<&str>, limit: usize) -> Vec<FraudEventRecord> {
+        let mut records = self
+            .events
+            .values()
+            .filter(|r| peer_id.is_none_or(|p| r.peer_id == p))
+            .cloned()
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| {
+            (b.block_number, b.log_index).cmp(&(a.block_number, a.log_index))
+        });
+        records.truncate(limit);
+        records
+    }
+}
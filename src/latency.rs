@@ -0,0 +1,194 @@
+//! Worker latency outlier detection: compares each worker's `total_time` with
+//! sibling workers that answered the same query hash on the same chunk.
+
+use crate::types::{LatencyVerdict, QueryTimingRow, WorkerLatencyStats};
+use std::collections::HashMap;
+
+/// Thresholds used to turn latency distributions into verdicts.
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyThresholds {
+    pub fast_ratio: f64,
+    pub slow_ratio: f64,
+    pub min_samples: usize,
+}
+
+fn median_u32(values: &mut [u32]) -> u32 {
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+fn percentile(sorted: &[u32], pct: usize) -> u32 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[(sorted.len() - 1) * pct / 100]
+}
+
+/// Group timings by `(hash, dataset, chunk_id)`, compare every response with
+/// the median of the *other* workers in the group and aggregate per worker.
+///
+/// Returns the number of comparable groups and the per-worker statistics,
+/// most suspicious first.
+pub fn analyze_latencies(
+    rows: &[QueryTimingRow],
+    thresholds: LatencyThresholds,
+) -> (usize, Vec<WorkerLatencyStats>) {
+    let mut groups = HashMap::<(&str, &str, &str), HashMap<&str, Vec<u32>>>::new();
+    for row in rows {
+        groups
+            .entry((row.hash.as_str(), row.dataset.as_str(), row.chunk_id.as_str()))
+            .or_default()
+            .entry(row.worker_id.as_str())
+            .or_default()
+            .push(row.total_time);
+    }
+
+    let mut comparable_groups = 0;
+    let mut per_worker = HashMap::<&str, (Vec<u32>, Vec<f64>)>::new();
+    for workers in groups.values() {
+        if workers.len() < 2 {
+            continue;
+        }
+        comparable_groups += 1;
+        let medians = workers
+            .iter()
+            .map(|(worker, times)| (*worker, median_u32(&mut times.clone())))
+            .collect::<HashMap<_, _>>();
+        for (worker, times) in workers {
+            let mut others = medians
+                .iter()
+                .filter(|(w, _)| **w != *worker)
+                .map(|(_, m)| *m)
+                .collect::<Vec<_>>();
+            let sibling_median = median_u32(&mut others);
+            if sibling_median == 0 {
+                continue;
+            }
+            let entry = per_worker.entry(*worker).or_default();
+            for time in times {
+                entry.0.push(*time);
+                entry.1.push(*time as f64 / sibling_median as f64);
+            }
+        }
+    }
+
+    let mut stats = per_worker
+        .into_iter()
+        .map(|(worker, (mut times, mut ratios))| {
+            times.sort_unstable();
+            ratios.sort_by(|a, b| a.total_cmp(b));
+            let samples = ratios.len();
+            let fast_samples = ratios.iter().filter(|r| **r < thresholds.fast_ratio).count();
+            let slow_samples = ratios.iter().filter(|r| **r > thresholds.slow_ratio).count();
+            let median_ratio = ratios[samples / 2];
+            let verdict = if samples < thresholds.min_samples {
+                LatencyVerdict::Normal
+            } else if fast_samples * 2 >= samples {
+                LatencyVerdict::SuspiciouslyFast
+            } else if median_ratio > thresholds.slow_ratio {
+                LatencyVerdict::ConsistentlySlow
+            } else {
+                LatencyVerdict::Normal
+            };
+            WorkerLatencyStats {
+                worker_id: worker.to_owned(),
+                samples,
+                fast_samples,
+                slow_samples,
+                p10_total_time: percentile(&times, 10),
+                p50_total_time: percentile(&times, 50),
+                p90_total_time: percentile(&times, 90),
+                median_ratio,
+                verdict,
+            }
+        })
+        .collect::<Vec<_>>();
+    stats.sort_by(|a, b| {
+        (a.verdict == LatencyVerdict::Normal)
+            .cmp(&(b.verdict == LatencyVerdict::Normal))
+            .then(a.median_ratio.total_cmp(&b.median_ratio))
+    });
+    (comparable_groups, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: LatencyThresholds = LatencyThresholds {
+        fast_ratio: 0.5,
+        slow_ratio: 2.0,
+        min_samples: 3,
+    };
+
+    /// `groups` queries answered by `worker` in `time` and by three siblings
+    /// in 100ms each.
+    fn rows(worker: &str, time: u32, groups: usize) -> Vec<QueryTimingRow> {
+        let mut rows = Vec::new();
+        for group in 0..groups {
+            for (worker_id, total_time) in [(worker, time), ("b", 100), ("c", 100), ("d", 100)] {
+                rows.push(QueryTimingRow {
+                    query_id: format!("{worker_id}-{group}"),
+                    worker_id: worker_id.to_owned(),
+                    hash: format!("hash-{group}"),
+                    dataset: "s3://dataset".to_owned(),
+                    chunk_id: "0000000000/0000000000-0000000100-00000000".to_owned(),
+                    total_time,
+                    collector_timestamp: 0,
+                });
+            }
+        }
+        rows
+    }
+
+    fn verdict_of(stats: &[WorkerLatencyStats], worker: &str) -> LatencyVerdict {
+        stats.iter().find(|s| s.worker_id == worker).unwrap().verdict
+    }
+
+    #[test]
+    fn fast_worker_is_flagged_first() {
+        let (groups, stats) = analyze_latencies(&rows("a", 40, 3), THRESHOLDS);
+        assert_eq!(groups, 3);
+        assert_eq!(stats[0].worker_id, "a");
+        assert_eq!(stats[0].verdict, LatencyVerdict::SuspiciouslyFast);
+        assert_eq!(stats[0].fast_samples, 3);
+        assert_eq!(verdict_of(&stats, "b"), LatencyVerdict::Normal);
+    }
+
+    #[test]
+    fn fast_ratio_boundary_is_not_fast() {
+        let (_, stats) = analyze_latencies(&rows("a", 50, 3), THRESHOLDS);
+        assert_eq!(verdict_of(&stats, "a"), LatencyVerdict::Normal);
+        let (_, stats) = analyze_latencies(&rows("a", 49, 3), THRESHOLDS);
+        assert_eq!(verdict_of(&stats, "a"), LatencyVerdict::SuspiciouslyFast);
+    }
+
+    #[test]
+    fn slow_ratio_boundary_is_not_slow() {
+        let (_, stats) = analyze_latencies(&rows("a", 200, 3), THRESHOLDS);
+        assert_eq!(verdict_of(&stats, "a"), LatencyVerdict::Normal);
+        assert_eq!(stats.iter().find(|s| s.worker_id == "a").unwrap().slow_samples, 0);
+        let (_, stats) = analyze_latencies(&rows("a", 201, 3), THRESHOLDS);
+        assert_eq!(verdict_of(&stats, "a"), LatencyVerdict::ConsistentlySlow);
+    }
+
+    #[test]
+    fn too_few_samples_stay_normal() {
+        let (_, stats) = analyze_latencies(&rows("a", 10, 2), THRESHOLDS);
+        let a = stats.iter().find(|s| s.worker_id == "a").unwrap();
+        assert_eq!(a.samples, 2);
+        assert_eq!(a.fast_samples, 2);
+        assert_eq!(a.verdict, LatencyVerdict::Normal);
+    }
+
+    #[test]
+    fn single_worker_groups_are_not_comparable() {
+        let rows = rows("a", 10, 3)
+            .into_iter()
+            .filter(|row| row.worker_id == "a")
+            .collect::<Vec<_>>();
+        let (groups, stats) = analyze_latencies(&rows, THRESHOLDS);
+        assert_eq!(groups, 0);
+        assert!(stats.is_empty());
+    }
+}
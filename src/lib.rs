@@ -1,17 +1,34 @@
+pub mod admin;
+pub mod assignment_cache;
+pub mod assignment_diff;
+pub mod assignment_resolver;
+pub mod assignment_source;
+pub mod checkpoint;
+pub mod cli;
+pub mod collusion;
+pub mod commitments;
 pub mod contracts;
 pub mod db;
+pub mod evidence;
+pub mod fraud_events;
+pub mod latency;
 pub mod loops;
 pub mod mpt;
 pub mod proof_storage;
+pub mod query_resolver;
+pub mod reputation;
 pub mod routes;
+pub mod rpc;
+pub mod safe;
 pub mod state;
+pub mod store;
+pub mod submission;
+pub mod transactions;
 pub mod types;
 pub mod zk;
 
 // Convenience re-exports
-pub use contracts::{
-    CommitmentHolder, ProvingManager, filter_eligible_queries, get_assignment_id_map, post_proof,
-};
+pub use contracts::{CommitmentHolder, ProvingManager, filter_eligible_queries, post_proof};
 pub use db::{
     find_odds_in_siblings, get_signatures, get_siblings_queries,
     get_siblings_queries_by_investigate_row, get_suspicious_hashes, investigate_hash,
@@ -2,15 +2,29 @@
 //! and creates ZK fraud proofs automatically.
 
 use crate::{
-    contracts::{filter_eligible_queries, get_assignment_id_map},
-    db::{find_odds_in_siblings, get_siblings_queries_by_investigate_row, get_suspicious_hashes, get_signatures, investigate_hash},
-    mpt::{make_mpt_proof, populate_trie},
+    assignment_resolver::AssignmentIdResolver,
+    assignment_source::AssignmentSource,
+    collusion::CollusionThresholds,
+    contracts::{
+        CommitmentMismatch, RootCheckCache, check_assignment_root, filter_eligible_queries,
+        primary_assignment_map,
+    },
+    db::{
+        find_odds_in_siblings, get_siblings_queries_by_investigate_row, get_signatures,
+        get_suspicious_hashes, get_worker_failures, investigate_hash,
+    },
+    evidence::select_evidence,
+    mpt::{AssignmentTrieCache, make_mpt_proof},
+    safe::propose_proof,
     state::InternalState,
-    types::{DiscoveryEvent, DiscoveryLoopProgress, PrivateProofData},
+    submission::publish_proof,
+    types::{
+        DiscoveryEvent, DiscoveryLoopProgress, PrivateProofData, QuorumRules,
+        ReputationEventKind,
+    },
     zk::{build_zk_proof, make_proof_data},
 };
 use clickhouse::Client;
-use eth_trie::{EthTrie, MemoryDB, Trie};
 use std::{
     collections::HashSet,
     sync::{Arc, Mutex},
@@ -126,9 +140,25 @@ pub fn start_discovery_loop(state: &InternalState) {
     let local_config = state.config.clone();
     let local_proof_storage = Arc::clone(&state.proof_storage);
     let local_progress = Arc::clone(&state.discovery_progress);
+    let local_agreement_graph = Arc::clone(&state.agreement_graph);
+    let local_reputation = Arc::clone(&state.reputation);
+    let local_assignment_cache = Arc::clone(&state.assignment_cache);
+    let local_rpc = Arc::clone(&state.rpc);
+    let local_submitter = state.submitter.clone();
+    let local_safe_proposer = state.safe_proposer.clone();
+    let quorum_rules = QuorumRules::from_args(&state.config);
+    let collusion_thresholds = CollusionThresholds::from_args(&state.config);
 
     tokio::spawn(async move {
+        let mut trie_cache = AssignmentTrieCache::new(
+            local_config.trie_cache_size,
+            local_config.assignment_max_decompressed_bytes,
+        );
+        let assignment_source = AssignmentSource::parse(&local_config.assignment_source);
+        let mut assignment_resolver =
+            AssignmentIdResolver::new(Arc::clone(&local_rpc), local_config.commiter_address);
         loop {
+            let mut root_checks = RootCheckCache::new();
             // ----------------------------------------------------------------
             // Start of a new iteration: reset events, current_stage; bump counter.
             // ----------------------------------------------------------------
@@ -143,8 +173,7 @@ pub fn start_discovery_loop(state: &InternalState) {
             let db_url = local_config.db_url.clone();
             let db_database = local_config.db_database.clone();
             let db_user = local_config.db_user.clone();
-            let db_password = local_config.db_password.clone();
-            let rpc_url = local_config.rpc_url.clone();
+            let db_password = local_config.db_password.clone().unwrap_or_default();
             let commiter_address = local_config.commiter_address;
 
             let client = Client::default()
@@ -158,6 +187,22 @@ pub fn start_discovery_loop(state: &InternalState) {
             let range_start_sec = range_end_sec - 24 * 3600 * 30;
             let start = Instant::now();
 
+            match get_worker_failures(&client, range_start_sec, range_end_sec).await {
+                Ok(rows) => {
+                    let mut reputation = local_reputation.lock().unwrap();
+                    for row in rows {
+                        reputation.set_availability_failures(&row.worker_id, row.failures);
+                    }
+                }
+                Err(err) => {
+                    push_error(
+                        &local_progress,
+                        0,
+                        format!("Got error while fetching worker failures: {err:?}"),
+                    );
+                }
+            }
+
             // ----------------------------------------------------------------
             // Stage 1: Fetch suspicious hashes
             // ----------------------------------------------------------------
@@ -254,7 +299,7 @@ pub fn start_discovery_loop(state: &InternalState) {
                     1,
                     format!("Finding oddities for hash {:?}", row.hash),
                 );
-                let odds = match find_odds_in_siblings(&siblings) {
+                let odds = match find_odds_in_siblings(&siblings, &quorum_rules) {
                     Ok(odds) => odds,
                     Err(err) => {
                         push_error(
@@ -268,17 +313,61 @@ pub fn start_discovery_loop(state: &InternalState) {
                 push_info(
                     &local_progress,
                     1,
-                    format!("Odd query id(s): {odds:?}"),
+                    format!(
+                        "Odd query id(s): {:?}",
+                        odds.iter().map(|odd| &odd.query_id).collect::<Vec<_>>()
+                    ),
                 );
 
+                let suspicious_majority = {
+                    let mut graph = local_agreement_graph.lock().unwrap();
+                    graph.record_group(&siblings);
+                    graph.suspicious_majority(&siblings, collusion_thresholds)
+                };
+                {
+                    // One event per oddity, once the collusion check decided
+                    // whether the accusation stands.
+                    let mut reputation = local_reputation.lock().unwrap();
+                    for odd in &odds {
+                        let (kind, detail) = match &suspicious_majority {
+                            Some(cluster) => (
+                                ReputationEventKind::Cleared,
+                                format!("majority dominated by cluster {:?}", cluster.workers),
+                            ),
+                            None => (
+                                ReputationEventKind::Divergence,
+                                format!("hash {} (confidence {:.2})", row.hash, odd.confidence),
+                            ),
+                        };
+                        reputation.record(&odd.worker_id, kind, Some(odd.query_id.clone()), detail);
+                    }
+                }
+                if let Some(cluster) = suspicious_majority {
+                    push_error(
+                        &local_progress,
+                        1,
+                        format!(
+                            "Hash {:?}: majority is dominated by suspected colluding cluster \
+                             {:?}, not proving against the minority",
+                            row.hash, cluster.workers
+                        ),
+                    );
+                    continue;
+                }
+
                 // --------------------------------------------------------
                 // Per oddity (query_id)
                 // --------------------------------------------------------
-                for query_id in odds {
+                for odd in odds {
+                    let query_id = odd.query_id;
+                    let odd_worker_id = odd.worker_id;
                     push_info(
                         &local_progress,
                         1,
-                        format!("Investigating oddity: {query_id:?}"),
+                        format!(
+                            "Investigating oddity: {query_id:?} (confidence {:.2})",
+                            odd.confidence
+                        ),
                     );
                     // Skip proof creation if a proof already exists for this query_id
                     {
@@ -302,20 +391,23 @@ pub fn start_discovery_loop(state: &InternalState) {
                         2,
                         format!("Resolving assignment-id map for query_id {query_id}"),
                     );
-                    let assignment_id_map =
-                        match get_assignment_id_map(&siblings, &rpc_url, commiter_address).await {
-                            Ok(map) => map,
-                            Err(err) => {
-                                push_error(
-                                    &local_progress,
-                                    2,
-                                    format!(
-                                        "query_id {query_id}: got {err:?} while querying contract"
-                                    ),
-                                );
-                                continue;
-                            }
-                        };
+                    let assignment_candidates = match assignment_resolver
+                        .candidates(&siblings, local_config.assignment_boundary_window)
+                        .await
+                    {
+                        Ok(map) => map,
+                        Err(err) => {
+                            push_error(
+                                &local_progress,
+                                2,
+                                format!(
+                                    "query_id {query_id}: got {err:?} while querying contract"
+                                ),
+                            );
+                            continue;
+                        }
+                    };
+                    let assignment_id_map = primary_assignment_map(&assignment_candidates);
 
                     let eligible_queries =
                         filter_eligible_queries(&siblings, &assignment_id_map, &query_id);
@@ -373,6 +465,47 @@ pub fn start_discovery_loop(state: &InternalState) {
                         continue;
                     };
 
+                    let selection = select_evidence(
+                        &eligible_queries,
+                        &assignment_id_map,
+                        &signatures,
+                        &query_id,
+                        NUMBER_OF_EVIDENCES_IN_ZK_PROOF,
+                    );
+                    for rejected in &selection.rejected {
+                        push_info(
+                            &local_progress,
+                            3,
+                            format!(
+                                "query_id {query_id}: rejected evidence {} ({}): {}",
+                                rejected.row.query_id, rejected.row.worker_id, rejected.reason
+                            ),
+                        );
+                    }
+                    if selection.candidates.len() < NUMBER_OF_EVIDENCES_IN_ZK_PROOF {
+                        push_error(
+                            &local_progress,
+                            2,
+                            format!(
+                                "query_id {query_id}: not enough valid evidence from distinct \
+                                 workers (got {}, rejected {})",
+                                selection.candidates.len(),
+                                selection.rejected.len()
+                            ),
+                        );
+                        continue;
+                    }
+                    push_info(
+                        &local_progress,
+                        2,
+                        format!(
+                            "query_id {query_id}: selected {} candidate(s), preferred set \
+                             needs {} assignment(s)",
+                            selection.candidates.len(),
+                            selection.assignments
+                        ),
+                    );
+
                     // Stage 7: Assemble proof data entries ----------------
                     push_stage(
                         &local_progress,
@@ -384,7 +517,7 @@ pub fn start_discovery_loop(state: &InternalState) {
                     let mut used_keys: HashSet<String> = Default::default();
                     let mut proof_data_list: Vec<PrivateProofData> = Default::default();
 
-                    for proof_row in &eligible_queries {
+                    for proof_row in selection.candidates {
                         if proof_data_list.len() >= NUMBER_OF_EVIDENCES_IN_ZK_PROOF {
                             break;
                         }
@@ -396,63 +529,142 @@ pub fn start_discovery_loop(state: &InternalState) {
                                 Some(res) => res,
                                 None => continue,
                             };
-                        let db = Arc::new(MemoryDB::new(true));
-                        let mut trie = EthTrie::new(db);
-                        let assignment_id = match assignment_id_map.get(&proof_row.query_id) {
+                        let candidates = match assignment_candidates.get(&proof_row.query_id) {
                             Some(v) => v,
                             None => continue,
                         };
-                        let network = local_config.network.clone();
-                        let assignment_url = format!(
-                            "https://metadata.sqd-datasets.io/assignments/{network}/{assignment_id}.fb.1.gz"
-                        );
-                        match populate_trie(assignment_url, &mut trie).await {
-                            Ok(_) => {}
-                            Err(err) => {
-                                push_error(
-                                    &local_progress,
-                                    3,
-                                    format!(
-                                        "query_id {query_id}: failed to build MPT for \
-                                         {assignment_id}: {err}"
-                                    ),
-                                );
-                                continue;
-                            }
-                        };
-                        let tree_root = match trie.root_hash() {
-                            Ok(root) => root.to_vec(),
-                            Err(err) => {
-                                push_error(
-                                    &local_progress,
-                                    3,
-                                    format!(
-                                        "query_id {query_id}: failed to calculate MPT root for \
-                                         {assignment_id}: {err}"
-                                    ),
-                                );
-                                continue;
-                            }
-                        };
-                        let mpt_proof = match make_mpt_proof(
-                            &mut trie,
-                            &proof_row.dataset_id,
-                            &proof_row.chunk_id,
-                            &proof_row.worker_id,
-                        ) {
-                            Ok(p) => p,
-                            Err(err) => {
-                                push_error(
-                                    &local_progress,
-                                    3,
-                                    format!(
-                                        "query_id {query_id}: failed to calculate MPT proof \
-                                         for {proof_row:?}: {err}"
-                                    ),
-                                );
-                                continue;
+                        let mut matched = None;
+                        for candidate in candidates {
+                            let assignment_id = &candidate.assignment_id;
+                            let location = match assignment_source
+                                .locate(&local_config.network, assignment_id)
+                            {
+                                Ok(location) => location,
+                                Err(err) => {
+                                    push_error(
+                                        &local_progress,
+                                        3,
+                                        format!("query_id {query_id}: {err}"),
+                                    );
+                                    continue;
+                                }
+                            };
+                            let cached = match trie_cache
+                                .get_or_build(
+                                    assignment_id,
+                                    &location,
+                                    Some(local_assignment_cache.as_ref()),
+                                )
+                                .await
+                            {
+                                Ok(cached) => cached,
+                                Err(err) => {
+                                    push_error(
+                                        &local_progress,
+                                        3,
+                                        format!(
+                                            "query_id {query_id}: failed to build MPT for \
+                                             {assignment_id}: {err}"
+                                        ),
+                                    );
+                                    continue;
+                                }
+                            };
+                            let tree_root = cached.root.clone();
+                            push_info(
+                                &local_progress,
+                                3,
+                                format!(
+                                    "Assignment {assignment_id}: {} compressed / {} decompressed \
+                                     bytes, {} chunks, loaded in {}ms{}, trie built in {}ms",
+                                    cached.metrics.compressed_bytes,
+                                    cached.metrics.decompressed_bytes,
+                                    cached.metrics.chunks,
+                                    cached.metrics.load_ms,
+                                    if cached.metrics.from_cache { " (cache)" } else { "" },
+                                    cached.metrics.build_ms
+                                ),
+                            );
+                            let mpt_proof = match make_mpt_proof(
+                                &mut cached.trie,
+                                &proof_row.dataset_id,
+                                &proof_row.chunk_id,
+                                &proof_row.worker_id,
+                            ) {
+                                Ok(p) => p,
+                                Err(err) => {
+                                    push_error(
+                                        &local_progress,
+                                        3,
+                                        format!(
+                                            "query_id {query_id}: {:?} candidate {assignment_id} \
+                                             rejected for {}: {err}",
+                                            candidate.kind, proof_row.query_id
+                                        ),
+                                    );
+                                    continue;
+                                }
+                            };
+                            // The contract verifies the root at the query's
+                            // timestamp, whichever probe found the candidate.
+                            match check_assignment_root(
+                                &local_rpc,
+                                commiter_address,
+                                assignment_id,
+                                &tree_root,
+                                proof_row.client_timestamp / 1000,
+                                &mut root_checks,
+                            )
+                            .await
+                            {
+                                Ok(()) => {}
+                                Err(err) if err.is::<CommitmentMismatch>() => {
+                                    push_error(
+                                        &local_progress,
+                                        3,
+                                        format!("query_id {query_id}: refusing assignment: {err}"),
+                                    );
+                                    continue;
+                                }
+                                Err(err) => {
+                                    push_error(
+                                        &local_progress,
+                                        3,
+                                        format!(
+                                            "query_id {query_id}: failed to check on-chain \
+                                             commitment for {assignment_id}: {err:?}"
+                                        ),
+                                    );
+                                    continue;
+                                }
                             }
+                            matched = Some((candidate, tree_root, mpt_proof));
+                            break;
+                        }
+                        let Some((candidate, tree_root, mpt_proof)) = matched else {
+                            push_error(
+                                &local_progress,
+                                3,
+                                format!(
+                                    "query_id {query_id}: none of {} candidate assignment(s) \
+                                     matched {}",
+                                    candidates.len(),
+                                    proof_row.query_id
+                                ),
+                            );
+                            continue;
                         };
+                        push_info(
+                            &local_progress,
+                            3,
+                            format!(
+                                "query_id {query_id}: {} matched assignment {} ({:?}, probed at {})",
+                                proof_row.query_id,
+                                candidate.assignment_id,
+                                candidate.kind,
+                                candidate.probe_timestamp_sec
+                            ),
+                        );
                         let proof = match make_proof_data(
                             proof_row,
                             result_hash,
@@ -532,8 +744,18 @@ pub fn start_discovery_loop(state: &InternalState) {
 
                     match proof_result {
                         Ok((proof_bytes, public_values)) => {
+                            let proposal = local_safe_proposer
+                                .as_ref()
+                                .map(|_| (proof_bytes.clone(), public_values.clone()));
                             let mut storage = local_proof_storage.lock().unwrap();
                             storage.add_proof(query_id.clone(), proof_bytes, public_values);
+                            drop(storage);
+                            local_reputation.lock().unwrap().record(
+                                &odd_worker_id,
+                                ReputationEventKind::Proven,
+                                Some(query_id.clone()),
+                                format!("hash {}", row.hash),
+                            );
                             push_info(
                                 &local_progress,
                                 2,
@@ -541,6 +763,64 @@ pub fn start_discovery_loop(state: &InternalState) {
                                     "Stored proof for query_id {query_id} in global proof storage"
                                 ),
                             );
+                            if let (Some(proposer), Some((proof_bytes, public_values))) =
+                                (&local_safe_proposer, proposal)
+                            {
+                                match propose_proof(
+                                    proposer,
+                                    &query_id,
+                                    proof_bytes,
+                                    public_values,
+                                    local_config.manager_address,
+                                    &local_config.config_name,
+                                )
+                                .await
+                                {
+                                    Ok(proposal) => push_info(
+                                        &local_progress,
+                                        2,
+                                        format!(
+                                            "Proposed Safe transaction {} (nonce {}) for query_id {query_id}",
+                                            proposal.contract_transaction_hash, proposal.nonce
+                                        ),
+                                    ),
+                                    Err(err) => push_error(
+                                        &local_progress,
+                                        2,
+                                        format!(
+                                            "Failed to propose Safe transaction for query_id {query_id}: {err:?}"
+                                        ),
+                                    ),
+                                }
+                            }
+                            if let Some(submitter) = &local_submitter {
+                                match publish_proof(
+                                    submitter,
+                                    &query_id,
+                                    &local_proof_storage,
+                                    local_config.manager_address,
+                                    &local_config.config_name,
+                                )
+                                .await
+                                {
+                                    Ok(receipt) => push_info(
+                                        &local_progress,
+                                        2,
+                                        format!(
+                                            "Published proof for query_id {query_id} in {} \
+                                             (block {}, fee {} wei)",
+                                            receipt.tx_hash, receipt.block_number, receipt.fee_wei
+                                        ),
+                                    ),
+                                    Err(err) => push_error(
+                                        &local_progress,
+                                        2,
+                                        format!(
+                                            "Failed to publish proof for query_id {query_id}: {err:?}"
+                                        ),
+                                    ),
+                                }
+                            }
                         }
                         Err(err) => {
                             push_error(
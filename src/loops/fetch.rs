@@ -1,42 +1,440 @@
-//! Background loop that subscribes to on-chain `FraudFound` events and marks
-//! the corresponding proofs as published in the shared proof storage.
+//! Background loop that indexes on-chain `FraudFound` events, records them in
+//! the fraud events store and marks the corresponding proofs as published in
+//! the shared proof storage.
+//!
+//! The last fully processed block is persisted, so after a restart or a
+//! reconnect the loop backfills exactly the gap between the checkpoint and the
+//! live subscription.
+//!
+//! Events are reorg-safe: a new event only marks its proof as pending.  Once
+//! its block has `fraud_confirmations` confirmations and is still canonical it
+//! becomes final; if the log is removed or its block replaced, the
+//! publication is rolled back.  The checkpoint never moves past a block that
+//! still holds pending events.
 
 use crate::{
+    checkpoint::BlockCheckpoint,
     contracts::ProvingManager,
-    db::get_query_id_by_worker_and_ts,
+    fraud_events::FraudEventStore,
+    proof_storage::ProofStorage,
+    query_resolver::{FraudLookup, QueryIdResolver},
+    reputation::ReputationStore,
     state::InternalState,
+    types::{FraudEventRecord, FraudEventStatus, PublicationStatus, ReputationEventKind},
+};
+use alloy::{
+    network::TransactionResponse,
+    primitives::B256,
+    providers::{DynProvider, Provider},
+    rpc::types::Log,
 };
 use clickhouse::Client;
-use std::{sync::Arc, time::Duration};
-use tokio::time::sleep;
-use tracing::{error, info};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{sleep, timeout};
+use tracing::{error, info, warn};
+
+/// Block range of a single `eth_getLogs` request (node limit is 50 000).
+pub(crate) const PAGE: u64 = 49_999;
+/// Blocks scanned on the very first run when no deployment block is configured.
+pub(crate) const DEFAULT_LOOKBACK: u64 = 5 * (PAGE + 1);
+/// How often pending events are checked for finality without new events.
+const FINALITY_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// (transaction hash, log index)
+type LogKey = (B256, u64);
+
+struct PendingFraudEvent {
+    block_number: u64,
+    block_hash: B256,
+    worker_id: String,
+    ts_ms: u64,
+    query_id: Option<String>,
+}
+
+/// Events seen on-chain but not final yet, and the stores they update.
+struct PendingFraudEvents {
+    confirmations: u64,
+    events: HashMap<LogKey, PendingFraudEvent>,
+    resolver: QueryIdResolver,
+    proof_storage: Arc<Mutex<ProofStorage>>,
+    reputation: Arc<Mutex<ReputationStore>>,
+    fraud_events: Arc<Mutex<FraudEventStore>>,
+}
+
+fn log_key(log: &Log) -> Option<LogKey> {
+    Some((log.transaction_hash?, log.log_index?))
+}
+
+impl PendingFraudEvents {
+    /// Events left pending by a previous run are tracked again, so that they
+    /// are finalised or rolled back like new ones.
+    fn new(confirmations: u64, resolver: QueryIdResolver, state: &InternalState) -> Self {
+        Self::with_stores(
+            confirmations,
+            resolver,
+            Arc::clone(&state.proof_storage),
+            Arc::clone(&state.reputation),
+            Arc::clone(&state.fraud_events),
+        )
+    }
+
+    fn with_stores(
+        confirmations: u64,
+        resolver: QueryIdResolver,
+        proof_storage: Arc<Mutex<ProofStorage>>,
+        reputation: Arc<Mutex<ReputationStore>>,
+        fraud_events: Arc<Mutex<FraudEventStore>>,
+    ) -> Self {
+        let mut events = HashMap::new();
+        for record in fraud_events.lock().unwrap().pending() {
+            let (Ok(tx_hash), Ok(block_hash)) = (
+                B256::from_str(&record.tx_hash),
+                B256::from_str(&record.block_hash),
+            ) else {
+                warn!("fetch_loop: ignoring stored FraudFound with malformed hashes");
+                continue;
+            };
+            events.insert(
+                (tx_hash, record.log_index),
+                PendingFraudEvent {
+                    block_number: record.block_number,
+                    block_hash,
+                    worker_id: record.peer_id,
+                    ts_ms: record.timestamp_ms,
+                    query_id: record.query_id,
+                },
+            );
+        }
+        PendingFraudEvents {
+            confirmations,
+            events,
+            resolver,
+            proof_storage,
+            reputation,
+            fraud_events,
+        }
+    }
+
+    /// Whether the log was already seen in `block_hash`.  A log re-read in
+    /// another block, e.g. by the backfill after a reconnect, means the old
+    /// block was reorged out, so its event is rolled back.
+    fn is_known(&mut self, key: &LogKey, block_hash: B256) -> bool {
+        match self.events.get(key) {
+            Some(pending) if pending.block_hash == block_hash => true,
+            Some(_) => {
+                self.rollback(key);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Record newly seen events and mark their proofs as pending.  The
+    /// `query_id`s of all events are looked up together.
+    async fn add(
+        &mut self,
+        logs: Vec<(ProvingManager::FraudFound, Log)>,
+        client: &Client,
+        provider: &DynProvider,
+    ) {
+        let mut new_events = Vec::new();
+        for (event, log) in logs {
+            let (Some(key), Some(block_number), Some(block_hash)) =
+                (log_key(&log), log.block_number, log.block_hash)
+            else {
+                warn!("fetch_loop: ignoring FraudFound log without block/tx information");
+                continue;
+            };
+            if self.is_known(&key, block_hash) {
+                continue;
+            }
+            let worker_id: String = event.peer_id.clone();
+            let ts_ms: u64 = event.timestamp.to::<u64>();
+            info!(
+                "fetch_loop: FraudFound event for worker_id={worker_id} ts_ms={ts_ms} in block {block_number}"
+            );
+            new_events.push((key, block_number, block_hash, worker_id, ts_ms));
+        }
+        if new_events.is_empty() {
+            return;
+        }
+
+        let lookups = new_events
+            .iter()
+            .map(|(key, _, block_hash, worker_id, ts_ms)| FraudLookup {
+                key: (block_hash.to_string(), key.1),
+                worker_id: worker_id.clone(),
+                ts_ms: *ts_ms,
+            })
+            .collect::<Vec<_>>();
+        let mut resolutions = self
+            .resolver
+            .resolve(client, &lookups, &self.proof_storage)
+            .await;
+
+        for (key, block_number, block_hash, worker_id, ts_ms) in new_events {
+            let resolution = resolutions
+                .remove(&(block_hash.to_string(), key.1))
+                .unwrap_or_else(|| Err("not looked up".to_owned()));
+            let sender = match provider.get_transaction_by_hash(key.0).await {
+                Ok(tx) => tx.map(|tx| tx.from().to_string()),
+                Err(err) => {
+                    warn!("fetch_loop: failed to fetch FraudFound transaction {}: {err:?}", key.0);
+                    None
+                }
+            };
+            let event = PendingFraudEvent {
+                block_number,
+                block_hash,
+                worker_id,
+                ts_ms,
+                query_id: None,
+            };
+            self.track(key, event, sender, resolution);
+        }
+    }
+
+    /// Start tracking a new event: store it and mark its proof as pending, or
+    /// schedule another lookup if its `query_id` is unknown.
+    fn track(
+        &mut self,
+        key: LogKey,
+        mut event: PendingFraudEvent,
+        sender: Option<String>,
+        resolution: Result<String, String>,
+    ) {
+        let store_key = (event.block_hash.to_string(), key.1);
+        match &resolution {
+            Ok(query_id) => {
+                info!("fetch_loop: query_id={query_id} published, awaiting confirmations");
+                self.proof_storage
+                    .lock()
+                    .unwrap()
+                    .upsert_publication(query_id.clone(), PublicationStatus::Pending);
+            }
+            Err(reason) => {
+                warn!(
+                    "fetch_loop: no query_id for worker_id={} ts_ms={} yet: {reason}",
+                    event.worker_id, event.ts_ms
+                );
+                self.resolver.schedule_retry(store_key.clone());
+            }
+        }
+        self.fraud_events.lock().unwrap().upsert(FraudEventRecord {
+            peer_id: event.worker_id.clone(),
+            timestamp_ms: event.ts_ms,
+            block_number: event.block_number,
+            block_hash: store_key.0,
+            tx_hash: key.0.to_string(),
+            log_index: key.1,
+            sender,
+            query_id: resolution.clone().ok(),
+            resolution_error: resolution.clone().err(),
+            status: FraudEventStatus::Pending,
+        });
+        event.query_id = resolution.ok();
+        self.events.insert(key, event);
+    }
+
+    /// Look up again the `query_id`s of events that could not be resolved
+    /// before, and apply the ones found to the proof storage.
+    async fn retry_unresolved(&mut self, client: &Client) {
+        let lookups = self
+            .fraud_events
+            .lock()
+            .unwrap()
+            .unresolved()
+            .into_iter()
+            .map(|record| FraudLookup {
+                key: (record.block_hash, record.log_index),
+                worker_id: record.peer_id,
+                ts_ms: record.timestamp_ms,
+            })
+            .filter(|lookup| self.resolver.is_due(&lookup.key))
+            .collect::<Vec<_>>();
+        if lookups.is_empty() {
+            return;
+        }
+        let resolutions = self
+            .resolver
+            .resolve(client, &lookups, &self.proof_storage)
+            .await;
+        for (store_key, resolution) in resolutions {
+            let found = resolution.clone().ok();
+            let status = self.fraud_events.lock().unwrap().set_resolution(
+                &store_key.0,
+                store_key.1,
+                resolution,
+            );
+            let Some(query_id) = found else {
+                self.resolver.schedule_retry(store_key);
+                continue;
+            };
+            self.resolver.resolved(&store_key);
+            info!("fetch_loop: resolved query_id={query_id} on retry");
+            let publication = match status {
+                Some(FraudEventStatus::Pending) => {
+                    if let Some(pending) = self.events.iter_mut().find_map(|(key, pending)| {
+                        (key.1 == store_key.1 && pending.block_hash.to_string() == store_key.0)
+                            .then_some(pending)
+                    }) {
+                        pending.query_id = Some(query_id.clone());
+                    }
+                    PublicationStatus::Pending
+                }
+                Some(FraudEventStatus::Final) => PublicationStatus::Final,
+                Some(FraudEventStatus::Reorged) | None => continue,
+            };
+            self.proof_storage
+                .lock()
+                .unwrap()
+                .upsert_publication(query_id, publication);
+        }
+    }
+
+    fn rollback(&mut self, key: &LogKey) {
+        let Some(pending) = self.events.remove(key) else {
+            return;
+        };
+        warn!(
+            "fetch_loop: FraudFound for worker_id={} in block {} was reorged out",
+            pending.worker_id, pending.block_number
+        );
+        if let Some(query_id) = &pending.query_id {
+            self.proof_storage
+                .lock()
+                .unwrap()
+                .rollback_publication(query_id);
+        }
+        let store_key = (pending.block_hash.to_string(), key.1);
+        self.resolver.resolved(&store_key);
+        self.fraud_events
+            .lock()
+            .unwrap()
+            .set_status(&store_key.0, store_key.1, FraudEventStatus::Reorged);
+    }
+
+    /// A log was removed by a reorg.
+    fn remove(&mut self, log: &Log) {
+        if let Some(key) = log_key(log) {
+            if self
+                .events
+                .get(&key)
+                .is_some_and(|pending| Some(pending.block_hash) == log.block_hash)
+            {
+                self.rollback(&key);
+            }
+        }
+    }
+
+    /// Finalise events with enough confirmations whose block is still
+    /// canonical, and roll back those whose block was replaced.
+    async fn settle(&mut self, provider: &DynProvider, head: u64) -> Result<(), anyhow::Error> {
+        for (key, block_number) in self.due(head) {
+            let canonical = provider
+                .get_block_by_number(block_number.into())
+                .await?
+                .map(|block| block.header.hash);
+            self.confirm(&key, canonical);
+        }
+        Ok(())
+    }
+
+    /// Events with enough confirmations at `head`, with their block numbers.
+    fn due(&self, head: u64) -> Vec<(LogKey, u64)> {
+        self.events
+            .iter()
+            .filter(|(_, pending)| pending.block_number + self.confirmations <= head)
+            .map(|(key, pending)| (*key, pending.block_number))
+            .collect()
+    }
+
+    /// Finalise a due event if its block is still the `canonical` one at its
+    /// height, or roll it back otherwise.
+    fn confirm(&mut self, key: &LogKey, canonical: Option<B256>) {
+        let Some(block_hash) = self.events.get(key).map(|pending| pending.block_hash) else {
+            return;
+        };
+        if canonical != Some(block_hash) {
+            self.rollback(key);
+            return;
+        }
+        if let Some(pending) = self.events.remove(key) {
+            self.reputation.lock().unwrap().record(
+                &pending.worker_id,
+                ReputationEventKind::FraudFound,
+                None,
+                format!("ts_ms={}", pending.ts_ms),
+            );
+            self.fraud_events.lock().unwrap().set_status(
+                &block_hash.to_string(),
+                key.1,
+                FraudEventStatus::Final,
+            );
+            if let Some(query_id) = pending.query_id {
+                info!("fetch_loop: marking query_id={query_id} as published (final)");
+                self.proof_storage
+                    .lock()
+                    .unwrap()
+                    .upsert_publication(query_id, PublicationStatus::Final);
+            }
+        }
+    }
+
+    /// Highest block that is final and below every pending event.
+    fn safe_block(&self, processed: u64, head: u64) -> u64 {
+        let mut safe = processed.min(head.saturating_sub(self.confirmations));
+        if let Some(lowest) = self.events.values().map(|p| p.block_number).min() {
+            safe = safe.min(lowest.saturating_sub(1));
+        }
+        safe
+    }
+}
 
 pub fn start_fetch_loop(state: &InternalState) {
-    use alloy::providers::{Provider, ProviderBuilder, WsConnect};
     use futures_util::StreamExt;
 
     let local_config = state.config.clone();
-    let local_proof_storage = Arc::clone(&state.proof_storage);
+    let local_rpc = Arc::clone(&state.rpc);
+    let checkpoint = BlockCheckpoint::new(&local_config.fraud_checkpoint_file);
+    let resolver = QueryIdResolver::new(
+        local_config.fraud_ts_tolerance_ms,
+        local_config.ts_tolerance,
+    );
+    let mut pending = PendingFraudEvents::new(local_config.fraud_confirmations, resolver, state);
 
     tokio::spawn(async move {
+        let mut saved_block = None;
+        let mut save_checkpoint = |block: u64| {
+            if saved_block.is_some_and(|saved| saved >= block) {
+                return;
+            }
+            match checkpoint.save(block) {
+                Ok(()) => saved_block = Some(block),
+                Err(err) => error!("fetch_loop: failed to persist checkpoint {block}: {err:?}"),
+            }
+        };
         loop {
-            let rpc_url = local_config.rpc_url.clone();
             let manager_address = local_config.manager_address;
             let db_url = local_config.db_url.clone();
             let db_database = local_config.db_database.clone();
             let db_user = local_config.db_user.clone();
-            let db_password = local_config.db_password.clone();
-            let ws = WsConnect::new(rpc_url.clone());
-            let provider = match ProviderBuilder::new().connect_ws(ws).await {
+            let db_password = local_config.db_password.clone().unwrap_or_default();
+            let provider = match local_rpc.ws_provider().await {
                 Ok(p) => p,
                 Err(err) => {
-                    error!("fetch_loop: failed to connect to WS RPC: {err:?}");
+                    error!("fetch_loop: no WS RPC endpoint available: {err:?}");
                     sleep(Duration::from_secs(10)).await;
                     continue;
                 }
             };
 
-            let proving_manager = ProvingManager::new(manager_address, provider);
+            let proving_manager = ProvingManager::new(manager_address, provider.clone());
 
             let client = Client::default()
                 .with_url(db_url)
@@ -46,23 +444,49 @@ pub fn start_fetch_loop(state: &InternalState) {
                 .with_option("max_execution_time", "60");
 
             // ------------------------------------------------------------------
-            // Backfill: fetch recent FraudFound events by walking backwards in
-            // 50 000-block pages (node limit), up to 5 pages (~last month).
+            // Subscribe first so that nothing emitted during the backfill is
+            // missed; events already seen are deduplicated by log key.
             // ------------------------------------------------------------------
-            const PAGE: u64 = 49_999;
-            const MAX_PAGES: u64 = 5;
-            info!("fetch_loop: fetching historical FraudFound events (up to {MAX_PAGES} pages)");
-            let latest_block = match proving_manager.provider().get_block_number().await {
+            let mut stream = match proving_manager.FraudFound_filter().subscribe().await {
+                Ok(s) => s.into_stream(),
+                Err(err) => {
+                    error!("fetch_loop: failed to subscribe to FraudFound events: {err:?}");
+                    local_rpc.reset_ws().await;
+                    sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            };
+            info!("fetch_loop: subscribed to FraudFound events");
+
+            // ------------------------------------------------------------------
+            // Backfill: walk forward from the checkpoint to the current head in
+            // PAGE-sized ranges, persisting progress after every page.
+            // ------------------------------------------------------------------
+            let latest_block = match provider.get_block_number().await {
                 Ok(n) => n,
                 Err(err) => {
                     error!("fetch_loop: failed to get latest block number: {err:?}");
-                    0
+                    local_rpc.reset_ws().await;
+                    sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+            };
+            let start_block = match checkpoint.load() {
+                Ok(Some(block)) => block + 1,
+                Ok(None) => local_config
+                    .manager_deployment_block
+                    .unwrap_or(latest_block.saturating_sub(DEFAULT_LOOKBACK)),
+                Err(err) => {
+                    error!("fetch_loop: unreadable checkpoint, not starting: {err:?}");
+                    sleep(Duration::from_secs(60)).await;
+                    continue;
                 }
             };
-            let mut page_end = latest_block;
-            'backfill: for _page in 0..MAX_PAGES {
-                let page_start = page_end.saturating_sub(PAGE);
-                info!("fetch_loop: querying FraudFound events blocks {page_start}..={page_end}");
+            info!("fetch_loop: backfilling FraudFound events blocks {start_block}..={latest_block}");
+            let mut page_start = start_block;
+            let mut healthy = true;
+            while page_start <= latest_block {
+                let page_end = (page_start + PAGE).min(latest_block);
                 match proving_manager
                     .FraudFound_filter()
                     .from_block(page_start)
@@ -75,105 +499,270 @@ pub fn start_fetch_loop(state: &InternalState) {
                             "fetch_loop: got {} historical FraudFound events in range {page_start}..={page_end}",
                             events.len()
                         );
-                        for (event, _log) in events {
-                            let worker_id: String = event.peer_id.clone();
-                            let ts_ms: u64 = event.timestamp.to::<u64>();
-                            match get_query_id_by_worker_and_ts(&client, &worker_id, ts_ms).await {
-                                Ok(Some(query_id)) => {
-                                    info!(
-                                        "fetch_loop: historical FraudFound – marking query_id={query_id} as published (worker_id={worker_id})"
-                                    );
-                                    let mut storage = local_proof_storage.lock().unwrap();
-                                    storage.upsert_published(query_id);
-                                }
-                                Ok(None) => {
-                                    error!(
-                                        "fetch_loop: historical FraudFound – no query_id found for worker_id={worker_id} ts_ms={ts_ms}"
-                                    );
-                                }
-                                Err(err) => {
-                                    error!(
-                                        "fetch_loop: historical FraudFound – clickhouse error for worker_id={worker_id} ts_ms={ts_ms}: {err:?}"
-                                    );
-                                }
-                            }
-                        }
+                        pending.add(events, &client, &provider).await;
                     }
                     Err(err) => {
                         error!(
                             "fetch_loop: failed to query historical FraudFound events ({page_start}..={page_end}): {err:?}"
                         );
-                        break 'backfill;
+                        healthy = false;
+                        break;
                     }
                 }
-                if page_start == 0 {
-                    break 'backfill;
+                if let Err(err) = pending.settle(&provider, latest_block).await {
+                    error!("fetch_loop: failed to check FraudFound finality: {err:?}");
+                    healthy = false;
+                    break;
                 }
-                page_end = page_start - 1;
+                save_checkpoint(pending.safe_block(page_end, latest_block));
+                page_start = page_end + 1;
             }
 
             // ------------------------------------------------------------------
-            // Live subscription: receive new FraudFound events going forward.
+            // Live subscription: receive new and removed FraudFound logs, and
+            // settle pending events as the chain advances.
             // ------------------------------------------------------------------
-            let event_filter = proving_manager.FraudFound_filter();
-            let mut stream = match event_filter.subscribe().await {
-                Ok(s) => s.into_stream(),
-                Err(err) => {
-                    error!("fetch_loop: failed to subscribe to FraudFound events: {err:?}");
-                    sleep(Duration::from_secs(10)).await;
-                    continue;
-                }
-            };
-
-            info!("fetch_loop: subscribed to FraudFound events");
-
-            loop {
-                match stream.next().await {
-                    None => {
+            while healthy {
+                match timeout(FINALITY_CHECK_INTERVAL, stream.next()).await {
+                    Err(_) => {}
+                    Ok(None) => {
                         info!("fetch_loop: FraudFound stream ended, reconnecting...");
-                        break;
+                        healthy = false;
                     }
-                    Some(Err(err)) => {
+                    Ok(Some(Err(err))) => {
                         error!("fetch_loop: error receiving FraudFound event: {err:?}");
-                        break;
+                        healthy = false;
                     }
-                    Some(Ok((event, _log))) => {
-                        let worker_id: String = event.peer_id.clone();
-                        let ts_ms: u64 = event.timestamp.to::<u64>();
-                        info!(
-                            "fetch_loop: received FraudFound event for worker_id={worker_id} ts_ms={ts_ms}"
-                        );
-
-                        let query_id = match get_query_id_by_worker_and_ts(
-                            &client,
-                            &worker_id,
-                            ts_ms,
-                        )
-                        .await
-                        {
-                            Ok(Some(qid)) => qid,
-                            Ok(None) => {
-                                error!(
-                                    "fetch_loop: no query_id found for worker_id={worker_id} ts_ms={ts_ms}"
-                                );
-                                continue;
-                            }
-                            Err(err) => {
-                                error!(
-                                    "fetch_loop: clickhouse error while fetching query_id for worker_id={worker_id} ts_ms={ts_ms}: {err:?}"
-                                );
-                                continue;
-                            }
-                        };
-
-                        info!(
-                            "fetch_loop: marking query_id={query_id} as published (worker_id={worker_id})"
-                        );
-                        let mut storage = local_proof_storage.lock().unwrap();
-                        storage.upsert_published(query_id);
+                    Ok(Some(Ok((event, log)))) => {
+                        if log.removed {
+                            pending.remove(&log);
+                        } else {
+                            pending.add(vec![(event, log)], &client, &provider).await;
+                        }
                     }
                 }
+                if !healthy {
+                    break;
+                }
+                let head = match provider.get_block_number().await {
+                    Ok(n) => n,
+                    Err(err) => {
+                        error!("fetch_loop: failed to get latest block number: {err:?}");
+                        break;
+                    }
+                };
+                if let Err(err) = pending.settle(&provider, head).await {
+                    error!("fetch_loop: failed to check FraudFound finality: {err:?}");
+                    break;
+                }
+                // Logs of blocks behind the confirmation depth have all been
+                // delivered by the subscription by now.
+                save_checkpoint(pending.safe_block(head, head));
+                pending.retry_unresolved(&client).await;
             }
+            local_rpc.reset_ws().await;
+            sleep(Duration::from_secs(10)).await;
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIRMATIONS: u64 = 10;
+
+    struct Fixture {
+        events: PendingFraudEvents,
+        proof_storage: Arc<Mutex<ProofStorage>>,
+        reputation: Arc<Mutex<ReputationStore>>,
+        fraud_events: Arc<Mutex<FraudEventStore>>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let proof_storage = Arc::new(Mutex::new(ProofStorage::new()));
+            let reputation = Arc::new(Mutex::new(ReputationStore::new()));
+            let fraud_events = Arc::new(Mutex::new(FraudEventStore::new()));
+            let events = PendingFraudEvents::with_stores(
+                CONFIRMATIONS,
+                QueryIdResolver::new(1000, 60),
+                Arc::clone(&proof_storage),
+                Arc::clone(&reputation),
+                Arc::clone(&fraud_events),
+            );
+            Fixture {
+                events,
+                proof_storage,
+                reputation,
+                fraud_events,
+            }
+        }
+
+        /// Track a resolved event of `query_id` in block `block_number`.
+        fn track(&mut self, key: LogKey, block_number: u64, block_hash: B256, query_id: &str) {
+            let event = PendingFraudEvent {
+                block_number,
+                block_hash,
+                worker_id: "worker".to_owned(),
+                ts_ms: 1_700_000_000_000,
+                query_id: None,
+            };
+            self.events.track(key, event, None, Ok(query_id.to_owned()));
+        }
+
+        fn publication(&self, query_id: &str) -> Option<PublicationStatus> {
+            self.proof_storage
+                .lock()
+                .unwrap()
+                .get(query_id)
+                .map(|proof| proof.publication)
+        }
+
+        fn status(&self, block_hash: B256, log_index: u64) -> Option<FraudEventStatus> {
+            self.fraud_events
+                .lock()
+                .unwrap()
+                .list(None, usize::MAX)
+                .into_iter()
+                .find(|r| r.block_hash == block_hash.to_string() && r.log_index == log_index)
+                .map(|r| r.status)
+        }
+    }
+
+    fn key(n: u8) -> LogKey {
+        (B256::repeat_byte(n), 0)
+    }
+
+    #[test]
+    fn reread_in_the_same_block_is_skipped() {
+        let mut f = Fixture::new();
+        f.track(key(1), 100, B256::repeat_byte(0xa), "q1");
+        assert!(f.events.is_known(&key(1), B256::repeat_byte(0xa)));
+        assert_eq!(f.publication("q1"), Some(PublicationStatus::Pending));
+        assert_eq!(
+            f.status(B256::repeat_byte(0xa), 0),
+            Some(FraudEventStatus::Pending)
+        );
+    }
+
+    #[test]
+    fn reread_under_another_block_hash_rolls_back() {
+        let mut f = Fixture::new();
+        f.proof_storage
+            .lock()
+            .unwrap()
+            .add_proof("q1".to_owned(), vec![1], vec![2]);
+        f.track(key(1), 100, B256::repeat_byte(0xa), "q1");
+        assert!(!f.events.is_known(&key(1), B256::repeat_byte(0xb)));
+        assert!(f.events.events.is_empty());
+        assert_eq!(f.publication("q1"), Some(PublicationStatus::Unpublished));
+        assert_eq!(
+            f.status(B256::repeat_byte(0xa), 0),
+            Some(FraudEventStatus::Reorged)
+        );
+
+        // The event is tracked again under its new block.
+        f.track(key(1), 101, B256::repeat_byte(0xb), "q1");
+        assert_eq!(f.publication("q1"), Some(PublicationStatus::Pending));
+        assert_eq!(
+            f.status(B256::repeat_byte(0xb), 0),
+            Some(FraudEventStatus::Pending)
+        );
+    }
+
+    #[test]
+    fn placeholder_of_reorged_publication_is_removed() {
+        let mut f = Fixture::new();
+        f.track(key(1), 100, B256::repeat_byte(0xa), "q1");
+        f.events.confirm(&key(1), Some(B256::repeat_byte(0xb)));
+        assert_eq!(f.publication("q1"), None);
+        assert_eq!(
+            f.status(B256::repeat_byte(0xa), 0),
+            Some(FraudEventStatus::Reorged)
+        );
+    }
+
+    #[test]
+    fn events_settle_after_confirmations() {
+        let mut f = Fixture::new();
+        let hash = B256::repeat_byte(0xa);
+        f.track(key(1), 100, hash, "q1");
+        assert!(f.events.due(100 + CONFIRMATIONS - 1).is_empty());
+        assert_eq!(f.events.due(100 + CONFIRMATIONS), vec![(key(1), 100)]);
+
+        f.events.confirm(&key(1), Some(hash));
+        assert!(f.events.events.is_empty());
+        assert_eq!(f.publication("q1"), Some(PublicationStatus::Final));
+        assert_eq!(f.status(hash, 0), Some(FraudEventStatus::Final));
+        assert_eq!(
+            f.reputation
+                .lock()
+                .unwrap()
+                .get("worker")
+                .unwrap()
+                .fraud_found,
+            1
+        );
+
+        // A late removal of a final event changes nothing.
+        f.events.confirm(&key(1), None);
+        assert_eq!(f.publication("q1"), Some(PublicationStatus::Final));
+    }
+
+    #[test]
+    fn safe_block_stays_below_pending_events() {
+        let mut f = Fixture::new();
+        assert_eq!(f.events.safe_block(500, 1000), 500);
+        assert_eq!(f.events.safe_block(1000, 1000), 1000 - CONFIRMATIONS);
+
+        f.track(key(1), 300, B256::repeat_byte(0xa), "q1");
+        f.track(key(2), 200, B256::repeat_byte(0xb), "q2");
+        assert_eq!(f.events.safe_block(1000, 1000), 199);
+
+        // Rolling back the lowest event lets the checkpoint pass its block,
+        // but not the other pending one.
+        f.events.confirm(&key(2), None);
+        assert_eq!(f.events.safe_block(1000, 1000), 299);
+        f.events.confirm(&key(1), Some(B256::repeat_byte(0xa)));
+        assert_eq!(f.events.safe_block(1000, 1000), 1000 - CONFIRMATIONS);
+    }
+
+    #[test]
+    fn rollback_below_the_checkpoint_is_still_applied() {
+        // Pending events restored from the store after a restart may lie
+        // behind the processed range; the checkpoint must stay below them so
+        // that they are re-read, and a reorg found there still rolls back.
+        let fraud_events = Arc::new(Mutex::new(FraudEventStore::new()));
+        let hash = B256::repeat_byte(0xa);
+        fraud_events.lock().unwrap().upsert(FraudEventRecord {
+            peer_id: "worker".to_owned(),
+            timestamp_ms: 1_700_000_000_000,
+            block_number: 50,
+            block_hash: hash.to_string(),
+            tx_hash: key(1).0.to_string(),
+            log_index: 0,
+            sender: None,
+            query_id: Some("q1".to_owned()),
+            resolution_error: None,
+            status: FraudEventStatus::Pending,
+        });
+        let proof_storage = Arc::new(Mutex::new(ProofStorage::new()));
+        proof_storage
+            .lock()
+            .unwrap()
+            .upsert_publication("q1".to_owned(), PublicationStatus::Pending);
+        let mut events = PendingFraudEvents::with_stores(
+            CONFIRMATIONS,
+            QueryIdResolver::new(1000, 60),
+            Arc::clone(&proof_storage),
+            Arc::new(Mutex::new(ReputationStore::new())),
+            Arc::clone(&fraud_events),
+        );
+        assert_eq!(events.safe_block(1000, 1000), 49);
+        assert!(!events.is_known(&key(1), B256::repeat_byte(0xb)));
+        assert!(proof_storage.lock().unwrap().get("q1").is_none());
+        assert!(fraud_events.lock().unwrap().pending().is_empty());
+        assert_eq!(events.safe_block(1000, 1000), 1000 - CONFIRMATIONS);
+    }
+}
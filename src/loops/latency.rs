@@ -0,0 +1,69 @@
+//! Background loop that periodically recomputes the worker latency report.
+
+use crate::{
+    db::get_query_timings,
+    latency::{LatencyThresholds, analyze_latencies},
+    state::InternalState,
+    types::{LatencyReport, LatencyVerdict},
+};
+use clickhouse::Client;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+pub fn start_latency_loop(state: &InternalState) {
+    let local_config = state.config.clone();
+    let local_report = Arc::clone(&state.latency_report);
+
+    tokio::spawn(async move {
+        let thresholds = LatencyThresholds {
+            fast_ratio: local_config.latency_fast_ratio,
+            slow_ratio: local_config.latency_slow_ratio,
+            min_samples: local_config.latency_min_samples,
+        };
+        loop {
+            let client = Client::default()
+                .with_url(local_config.db_url.clone())
+                .with_database(local_config.db_database.clone())
+                .with_user(local_config.db_user.clone())
+                .with_password(local_config.db_password.clone().unwrap_or_default())
+                .with_option("max_execution_time", "240");
+
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let range_end_sec = now as u32;
+            let range_start_sec = range_end_sec.saturating_sub(local_config.latency_window as u32);
+
+            match get_query_timings(&client, range_start_sec, range_end_sec).await {
+                Ok(rows) => {
+                    let (groups, workers) = analyze_latencies(&rows, thresholds);
+                    let flagged = workers
+                        .iter()
+                        .filter(|w| w.verdict != LatencyVerdict::Normal)
+                        .count();
+                    info!(
+                        "latency_loop: analysed {} timings in {groups} groups, {flagged} worker(s) flagged",
+                        rows.len()
+                    );
+                    *local_report.lock().unwrap() = LatencyReport {
+                        generated_at: now,
+                        range_start_sec,
+                        range_end_sec,
+                        groups,
+                        workers,
+                    };
+                }
+                Err(err) => {
+                    error!("latency_loop: failed to fetch query timings: {err:?}");
+                }
+            }
+
+            sleep(Duration::from_secs(local_config.latency_interval)).await;
+        }
+    });
+}
@@ -1,2 +1,6 @@
 pub mod discovery;
 pub mod fetch;
+pub mod latency;
+pub mod reputation;
+pub mod rpc_health;
+pub mod transactions;
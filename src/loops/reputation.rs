@@ -0,0 +1,26 @@
+//! Background loop that writes changed worker reputations to disk.
+
+use crate::state::InternalState;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::error;
+
+pub fn start_reputation_loop(state: &InternalState) {
+    let local_reputation = Arc::clone(&state.reputation);
+    let interval = Duration::from_secs(state.config.reputation_flush_interval);
+
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            let Some(snapshot) = local_reputation.lock().unwrap().snapshot() else {
+                continue;
+            };
+            let version = snapshot.version();
+            match tokio::task::spawn_blocking(move || snapshot.write()).await {
+                Ok(Ok(())) => local_reputation.lock().unwrap().mark_persisted(version),
+                Ok(Err(err)) => error!("reputation: failed to persist: {err:?}"),
+                Err(err) => error!("reputation: persist task failed: {err:?}"),
+            }
+        }
+    });
+}
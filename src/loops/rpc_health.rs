@@ -0,0 +1,17 @@
+//! Background loop that keeps the shared RPC providers healthy.
+
+use crate::state::InternalState;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+
+pub fn start_rpc_health_loop(state: &InternalState) {
+    let local_rpc = Arc::clone(&state.rpc);
+    let interval = Duration::from_secs(state.config.rpc_health_interval);
+
+    tokio::spawn(async move {
+        loop {
+            sleep(interval).await;
+            local_rpc.check_health().await;
+        }
+    });
+}
@@ -0,0 +1,156 @@
+//! Background loop that indexes `verifyAndEmit` transactions to the
+//! ProvingManager.
+//!
+//! Every block is scanned through its receipts, so transactions that reverted
+//! and emitted no logs are listed as well, with `success = false`.  The
+//! calldata of each transaction to the contract is fetched to keep only
+//! `verifyAndEmit` calls.  Only blocks with `transactions_confirmations`
+//! confirmations are indexed, so reorgs never have to be undone.
+
+use crate::{
+    checkpoint::BlockCheckpoint,
+    contracts::ProvingManager,
+    loops::fetch::DEFAULT_LOOKBACK,
+    state::InternalState,
+    transactions::TransactionStore,
+    types::SubmittedTransaction,
+};
+use alloy::{
+    consensus::Transaction,
+    network::{ReceiptResponse, TransactionResponse},
+    primitives::Address,
+    providers::{DynProvider, Provider},
+    sol_types::{SolCall, SolEvent},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+/// Blocks scanned between two checkpoint saves; every block costs one
+/// `eth_getBlockReceipts` request.
+const PAGE: u64 = 1_000;
+
+/// Index the `verifyAndEmit` transactions of `block_number`, returning them.
+async fn index_block(
+    provider: &DynProvider,
+    manager_address: Address,
+    block_number: u64,
+    store: &Mutex<TransactionStore>,
+) -> Result<Vec<SubmittedTransaction>, anyhow::Error> {
+    let receipts = provider
+        .get_block_receipts(block_number.into())
+        .await?
+        .unwrap_or_default();
+    let mut block_timestamp = None;
+    let mut found = Vec::new();
+    for receipt in receipts {
+        if receipt.to() != Some(manager_address) {
+            continue;
+        }
+        let tx_hash = receipt.transaction_hash();
+        if store.lock().unwrap().contains(&tx_hash.to_string()) {
+            continue;
+        }
+        let Some(tx) = provider.get_transaction_by_hash(tx_hash).await? else {
+            continue;
+        };
+        let Ok(call) = ProvingManager::verifyAndEmitCall::abi_decode(tx.input()) else {
+            continue;
+        };
+        if block_timestamp.is_none() {
+            block_timestamp = provider
+                .get_block_by_number(block_number.into())
+                .await?
+                .map(|block| block.header.timestamp);
+        }
+        let fraud_found = receipt
+            .inner
+            .logs()
+            .iter()
+            .filter(|log| {
+                log.address() == manager_address
+                    && log.topic0() == Some(&ProvingManager::FraudFound::SIGNATURE_HASH)
+            })
+            .count();
+        found.push(SubmittedTransaction {
+            tx_hash: tx_hash.to_string(),
+            sender: tx.from().to_string(),
+            block_number,
+            block_timestamp,
+            config_name: call.config_name,
+            success: receipt.status(),
+            gas_used: receipt.gas_used(),
+            fee_wei: (receipt.gas_used() as u128 * receipt.effective_gas_price()).to_string(),
+            fraud_found,
+        });
+    }
+    Ok(found)
+}
+
+/// Index `from..=to`, returning the number of new transactions.
+async fn index_range(
+    provider: &DynProvider,
+    manager_address: Address,
+    from: u64,
+    to: u64,
+    store: &Mutex<TransactionStore>,
+) -> Result<usize, anyhow::Error> {
+    let mut found = Vec::new();
+    for block_number in from..=to {
+        found.extend(index_block(provider, manager_address, block_number, store).await?);
+    }
+    let count = found.len();
+    store.lock().unwrap().extend(found);
+    Ok(count)
+}
+
+pub fn start_transactions_loop(state: &InternalState) {
+    let local_config = state.config.clone();
+    let local_rpc = Arc::clone(&state.rpc);
+    let local_store = Arc::clone(&state.transactions);
+    let checkpoint = BlockCheckpoint::new(&local_config.transactions_checkpoint_file);
+
+    tokio::spawn(async move {
+        loop {
+            let result = async {
+                let provider = local_rpc.provider().await?;
+                let head = provider.get_block_number().await?;
+                let safe_head = head.saturating_sub(local_config.transactions_confirmations);
+                let mut page_start = match checkpoint.load()? {
+                    Some(block) => block + 1,
+                    None => local_config
+                        .manager_deployment_block
+                        .unwrap_or(safe_head.saturating_sub(DEFAULT_LOOKBACK)),
+                };
+                while page_start <= safe_head {
+                    let page_end = (page_start + PAGE).min(safe_head);
+                    let count = index_range(
+                        &provider,
+                        local_config.manager_address,
+                        page_start,
+                        page_end,
+                        &local_store,
+                    )
+                    .await?;
+                    if count > 0 {
+                        info!(
+                            "transactions_loop: indexed {count} verifyAndEmit transaction(s) in {page_start}..={page_end}"
+                        );
+                    }
+                    checkpoint.save(page_end)?;
+                    page_start = page_end + 1;
+                }
+                Ok::<(), anyhow::Error>(())
+            }
+            .await;
+            if let Err(err) = result {
+                error!("transactions_loop: {err:?}");
+                local_rpc.reset().await;
+            }
+            sleep(Duration::from_secs(local_config.transactions_interval)).await;
+        }
+    });
+}
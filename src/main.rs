@@ -3,16 +3,31 @@ extern crate rocket;
 
 use clap::Parser;
 use snoopy::{
+    assignment_cache::AssignmentCache,
+    cli::run_command,
+    collusion::AgreementGraph,
+    fraud_events::FraudEventStore,
     loops::{
         discovery::start_discovery_loop,
         fetch::start_fetch_loop,
+        latency::start_latency_loop,
+        reputation::start_reputation_loop,
+        rpc_health::start_rpc_health_loop,
+        transactions::start_transactions_loop,
     },
     proof_storage::ProofStorage,
+    reputation::ReputationStore,
+    rpc::RpcPool,
+    safe::SafeProposer,
+    submission::Submitter,
+    transactions::TransactionStore,
     routes::{
-        app_js, get_all_proofs, get_discovery_progress, get_metadata, index, styles,
+        app_js, get_all_proofs, get_collusion_report, get_discovery_progress, get_latency_report, get_metadata,
+        get_fraud_events, get_transactions, get_worker, get_worker_latency, get_workers, index,
+        styles,
     },
     state::InternalState,
-    types::{Args, DiscoveryLoopProgress},
+    types::{Args, DiscoveryLoopProgress, LatencyReport, SubmissionMode},
 };
 use std::sync::{Arc, Mutex};
 use tikv_jemallocator::Jemalloc;
@@ -27,13 +42,67 @@ async fn main() -> Result<(), Box<rocket::Error>> {
         .install_default()
         .expect("should be able to install the default crypto provider");
     let args = Args::parse();
+    if let Some(command) = args.command.clone() {
+        if let Err(err) = run_command(command, &args).await {
+            eprintln!("{err:?}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.db_password.is_none() {
+        eprintln!("--db-password (or DB_PASSWORD) is required to run the server");
+        std::process::exit(2);
+    }
+    let assignment_cache =
+        AssignmentCache::open(&args.assignment_cache_dir, args.assignment_cache_max_bytes)
+            .expect("should be able to open the assignment cache directory");
+    let fraud_events = FraudEventStore::open(&args.fraud_events_file)
+        .expect("should be able to load the FraudFound events file");
+    let transactions = TransactionStore::open(&args.transactions_file)
+        .expect("should be able to load the transactions file");
+    let reputation = ReputationStore::open(&args.reputation_file)
+        .expect("should be able to load the reputation file");
+    let rpc = Arc::new(RpcPool::from_args(&args));
+    let submitter = match args.submission_mode {
+        SubmissionMode::Direct => match Submitter::from_args(Arc::clone(&rpc), &args) {
+            Ok(submitter) => Some(Arc::new(submitter)),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let safe_proposer = match args.submission_mode {
+        SubmissionMode::Safe => match SafeProposer::from_args(Arc::clone(&rpc), &args) {
+            Ok(proposer) => Some(Arc::new(proposer)),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
     let state = InternalState {
         proof_storage: Arc::new(Mutex::new(ProofStorage::new())),
         discovery_progress: Arc::new(Mutex::new(DiscoveryLoopProgress::default())),
+        latency_report: Arc::new(Mutex::new(LatencyReport::default())),
+        agreement_graph: Arc::new(Mutex::new(AgreementGraph::new())),
+        reputation: Arc::new(Mutex::new(reputation)),
+        assignment_cache: Arc::new(assignment_cache),
+        rpc,
+        fraud_events: Arc::new(Mutex::new(fraud_events)),
+        transactions: Arc::new(Mutex::new(transactions)),
+        submitter,
+        safe_proposer,
         config: args,
     };
     start_discovery_loop(&state);
     start_fetch_loop(&state);
+    start_latency_loop(&state);
+    start_reputation_loop(&state);
+    start_rpc_health_loop(&state);
+    start_transactions_loop(&state);
     let _ = rocket::build()
         .manage(state)
         .mount(
@@ -44,7 +113,14 @@ async fn main() -> Result<(), Box<rocket::Error>> {
                 app_js,
                 get_metadata,
                 get_all_proofs,
-                get_discovery_progress
+                get_discovery_progress,
+                get_latency_report,
+                get_worker_latency,
+                get_collusion_report,
+                get_workers,
+                get_worker,
+                get_fraud_events,
+                get_transactions
             ],
         )
         .launch()
@@ -1,81 +1,585 @@
-//! Merkle Patricia Trie helpers: populate from assignment data and generate
-//! inclusion proofs.
+//! Merkle Patricia Trie helpers: populate from assignment data, generate
+//! inclusion proofs and verify them.
+//!
+//! # Key derivation scheme (version 1)
+//!
+//! Every chunk of every dataset in an assignment becomes one trie entry:
+//!
+//! * key: `keccak256(utf8("{dataset_id}|{chunk_id}"))`, all 32 bytes;
+//! * value: base58 worker ids assigned to the chunk, sorted and joined by `|`.
+//!
+//! The scheme is identified by [`MPT_KEY_SCHEME_VERSION`], which is stored
+//! with every generated proof.  Any change to the key or value layout must
+//! bump it, since the SP1 program and external verifiers derive the same key.
+//!
+//! Proofs are requested from the trie with the first [`MPT_PROOF_KEY_PREFIX_LEN`]
+//! bytes of the key only.  This routes to the same leaf as the full key, since
+//! a leaf stores the remainder of its key, and is what the SP1 program
+//! receives in `PrivateProofData::mpt_proof`.  [`verify_mpt_proof`] checks the
+//! path against the root with the *full* key, so a proof for a different leaf
+//! sharing the prefix is rejected.
 
+use crate::{
+    assignment_cache::AssignmentCache,
+    assignment_source::AssignmentLocation,
+    types::AssignmentLoadMetrics,
+};
 use anyhow::anyhow;
 use eth_trie::{EthTrie, MemoryDB, Trie};
-use flate2::read::GzDecoder;
 use sqd_assignments::Assignment;
-use std::{fs::File, io::Read};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 use tiny_keccak::{Hasher, Keccak};
+use tracing::info;
 
-pub async fn populate_trie(
-    assignment_url: String,
-    trie: &mut EthTrie<MemoryDB>,
-) -> Result<(), anyhow::Error> {
-    let buf = &mut Default::default();
-    if assignment_url.starts_with("http") {
-        let response_assignment = reqwest::get(assignment_url).await?;
-        let compressed_assignment = response_assignment.bytes().await?;
-        let mut decoder = GzDecoder::new(&compressed_assignment[..]);
-        decoder.read_to_end(buf)?;
-    } else {
-        let file = File::open(assignment_url)?;
-        let mut decoder = GzDecoder::new(file);
-        decoder.read_to_end(buf)?;
-    }
+/// Version of the key derivation scheme described in the module docs.
+pub const MPT_KEY_SCHEME_VERSION: u8 = 1;
+
+/// Number of key bytes used when requesting a proof from the trie.
+pub const MPT_PROOF_KEY_PREFIX_LEN: usize = 8;
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut keccak = Keccak::v256();
+    keccak.update(data);
+    let mut bytes = [0u8; 32];
+    keccak.finalize(&mut bytes);
+    bytes
+}
+
+/// Trie key of a `(dataset, chunk)` pair.
+pub fn mpt_key(dataset_id: &str, chunk_id: &str) -> [u8; 32] {
+    keccak256(format!("{dataset_id}|{chunk_id}").as_bytes())
+}
 
-    let assignment = Assignment::from_owned_unchecked(buf.to_vec());
+/// Trie value for the set of workers assigned to a chunk.
+pub fn mpt_value(workers: &[String]) -> Vec<u8> {
+    let mut workers = workers.to_vec();
+    workers.sort();
+    workers.join("|").into_bytes()
+}
+
+/// Load and validate the assignment at `location`, going through `cache` when
+/// one is given.
+///
+/// The download is streamed into the gzip decoder, but the decompressed
+/// flatbuffer is kept in memory whole: its size, and so the memory used, is
+/// capped at `max_bytes` (`--assignment-max-decompressed-bytes`).  The
+/// flatbuffer is validated before it is returned.  The returned metrics have
+/// `chunks` and `build_ms` unset.
+pub async fn load_assignment(
+    location: &AssignmentLocation,
+    cache: Option<&AssignmentCache>,
+    max_bytes: u64,
+) -> Result<(Assignment, AssignmentLoadMetrics), anyhow::Error> {
+    let started = Instant::now();
+    let decoded = match cache {
+        Some(cache) => cache.load(location, max_bytes).await?,
+        None => location.load(max_bytes).await?,
+    };
+    let mut metrics = AssignmentLoadMetrics {
+        location: location.to_string(),
+        from_cache: decoded.from_cache,
+        compressed_bytes: decoded.compressed_bytes,
+        decompressed_bytes: decoded.bytes.len() as u64,
+        chunks: 0,
+        load_ms: 0,
+        build_ms: 0,
+    };
+    let assignment = Assignment::from_owned(decoded.bytes)
+        .map_err(|err| anyhow!("Invalid assignment flatbuffer at {location}: {err}"))?;
+    metrics.load_ms = started.elapsed().as_millis() as u64;
+    Ok((assignment, metrics))
+}
 
-    let workers = assignment
+/// All workers of `assignment` as base58 peer ids, in assignment order.
+pub fn assignment_workers(assignment: &Assignment) -> Vec<String> {
+    assignment
         .workers()
         .iter()
         .map(|worker| bs58::encode(&worker.worker_id().0).into_string())
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+/// Call `f(dataset_id, chunk_id, workers)` for every chunk of `assignment`,
+/// with workers as base58 peer ids.  A worker index outside the worker list
+/// is reported as an error.
+pub fn for_each_chunk(
+    assignment: &Assignment,
+    mut f: impl FnMut(&str, &str, Vec<String>) -> Result<(), anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let workers = assignment_workers(assignment);
 
     for dataset in assignment.datasets() {
         let prefix = &dataset.id();
         for chunk in dataset.chunks() {
-            let id = &chunk.id();
-            let key = format!("{prefix}|{id}");
-            let mut workers = chunk
+            let workers = chunk
                 .worker_indexes()
                 .iter()
-                .map(|idx| workers[idx as usize].clone())
-                .collect::<Vec<String>>();
-            workers.sort();
-            let val = workers.join("|");
-
-            let mut keccak = Keccak::v256();
-            keccak.update(key.as_bytes());
-            let mut bytes = [0u8; 32];
-            keccak.finalize(&mut bytes);
-
-            trie.insert(&bytes, val.as_bytes())?;
+                .map(|idx| {
+                    workers.get(idx as usize).cloned().ok_or_else(|| {
+                        anyhow!(
+                            "Worker index {idx} out of range ({} workers) in chunk {} of {prefix}",
+                            workers.len(),
+                            chunk.id()
+                        )
+                    })
+                })
+                .collect::<Result<Vec<String>, _>>()?;
+            f(prefix, &chunk.id(), workers)?;
         }
     }
     Ok(())
 }
 
+/// Insert the entry of one chunk into `trie`.
+pub fn insert_chunk(
+    trie: &mut EthTrie<MemoryDB>,
+    dataset_id: &str,
+    chunk_id: &str,
+    workers: &[String],
+) -> Result<(), anyhow::Error> {
+    trie.insert(&mpt_key(dataset_id, chunk_id), &mpt_value(workers))?;
+    Ok(())
+}
+
+/// Load the assignment at `location` into `trie`, going through `cache` when
+/// one is given.  See [`load_assignment`].
+pub async fn populate_trie(
+    location: &AssignmentLocation,
+    trie: &mut EthTrie<MemoryDB>,
+    cache: Option<&AssignmentCache>,
+    max_bytes: u64,
+) -> Result<AssignmentLoadMetrics, anyhow::Error> {
+    let (assignment, mut metrics) = load_assignment(location, cache, max_bytes).await?;
+    let started = Instant::now();
+    for_each_chunk(&assignment, |dataset_id, chunk_id, workers| {
+        insert_chunk(trie, dataset_id, chunk_id, &workers)?;
+        metrics.chunks += 1;
+        Ok(())
+    })?;
+    metrics.build_ms = started.elapsed().as_millis() as u64;
+    info!("Loaded assignment: {metrics:?}");
+    Ok(metrics)
+}
+
+/// Build a proof that `worker_id` is assigned to `dataset_id`/`chunk_id` and
+/// verify it against the trie root before returning it.
 pub fn make_mpt_proof(
     trie: &mut EthTrie<MemoryDB>,
     dataset_id: &String,
     chunk_id: &String,
     worker_id: &String,
 ) -> Result<Vec<Vec<u8>>, anyhow::Error> {
-    let key = format!("{dataset_id}|{chunk_id}");
-    let mut keccak = Keccak::v256();
-    keccak.update(key.as_bytes());
-    let mut bytes = [0u8; 32];
-    keccak.finalize(&mut bytes);
-    let trie_key = &bytes[0..8];
-    let mpt_proof = trie.get_proof(trie_key)?;
-    let leaf = mpt_proof.last().ok_or(anyhow!("Empty leaf in proof"))?;
-    let rlp_leaf: rlp::Rlp<'_> = rlp::Rlp::new(leaf);
-    let payload: String = rlp_leaf.val_at(1)?;
-    let parts = payload.split("|").map(|v| v.to_owned()).collect::<Vec<_>>();
-    if parts.contains(worker_id) {
-        Ok(mpt_proof)
+    let root = trie.root_hash()?;
+    let key = mpt_key(dataset_id, chunk_id);
+    let mpt_proof = trie.get_proof(&key[..MPT_PROOF_KEY_PREFIX_LEN])?;
+    verify_mpt_proof(
+        root.as_ref(),
+        MPT_KEY_SCHEME_VERSION,
+        dataset_id,
+        chunk_id,
+        worker_id,
+        &mpt_proof,
+    )?;
+    Ok(mpt_proof)
+}
+
+/// Decode a hex-prefix encoded path into `(is_leaf, nibbles)`.
+fn decode_path(path: &[u8]) -> Result<(bool, Vec<u8>), anyhow::Error> {
+    let first = *path.first().ok_or(anyhow!("Empty node path"))?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(anyhow!("Invalid node path flag {flag}"));
+    }
+    let mut nibbles = Vec::with_capacity(path.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    for byte in &path[1..] {
+        nibbles.push(byte >> 4);
+        nibbles.push(byte & 0x0f);
+    }
+    Ok((flag & 2 == 2, nibbles))
+}
+
+/// Reference to a child node: a 32-byte hash, or the raw encoding of a node
+/// shorter than 32 bytes that is embedded in its parent.
+fn child_ref(item: &rlp::Rlp<'_>) -> Result<Vec<u8>, anyhow::Error> {
+    if item.is_list() {
+        Ok(item.as_raw().to_vec())
+    } else {
+        let data = item.data()?;
+        if data.is_empty() {
+            Err(anyhow!("Key is not present in the trie"))
+        } else {
+            Ok(data.to_vec())
+        }
+    }
+}
+
+/// Walk `proof` from `root` along the full 32-byte `key` and return the value
+/// stored at the leaf.
+pub fn verify_proof_path(
+    root: &[u8],
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Vec<u8>, anyhow::Error> {
+    let nibbles = key
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect::<Vec<_>>();
+    let mut expected = root.to_vec();
+    let mut pos = 0;
+    for node in proof {
+        let matches = if expected.len() == 32 {
+            keccak256(node)[..] == expected[..]
+        } else {
+            node[..] == expected[..]
+        };
+        if !matches {
+            return Err(anyhow!("Proof node does not match its parent reference"));
+        }
+        let rlp_node = rlp::Rlp::new(node);
+        match rlp_node.item_count()? {
+            17 => {
+                if pos == nibbles.len() {
+                    return Ok(rlp_node.at(16)?.data()?.to_vec());
+                }
+                expected = child_ref(&rlp_node.at(nibbles[pos] as usize)?)?;
+                pos += 1;
+            }
+            2 => {
+                let (is_leaf, path) = decode_path(rlp_node.at(0)?.data()?)?;
+                if !nibbles[pos..].starts_with(&path) {
+                    return Err(anyhow!("Key is not present in the trie"));
+                }
+                pos += path.len();
+                if is_leaf {
+                    if pos != nibbles.len() {
+                        return Err(anyhow!("Leaf key is shorter than the requested key"));
+                    }
+                    return Ok(rlp_node.at(1)?.data()?.to_vec());
+                }
+                expected = child_ref(&rlp_node.at(1)?)?;
+            }
+            n => return Err(anyhow!("Unexpected node with {n} items")),
+        }
+    }
+    Err(anyhow!("Incomplete proof"))
+}
+
+/// Verify that `proof` proves, under `root` and key scheme `scheme_version`,
+/// that `worker_id` is assigned to `dataset_id`/`chunk_id`.  Returns all
+/// workers assigned to the chunk.
+pub fn verify_mpt_proof(
+    root: &[u8],
+    scheme_version: u8,
+    dataset_id: &str,
+    chunk_id: &str,
+    worker_id: &str,
+    proof: &[Vec<u8>],
+) -> Result<Vec<String>, anyhow::Error> {
+    if scheme_version != MPT_KEY_SCHEME_VERSION {
+        return Err(anyhow!(
+            "Unsupported MPT key scheme version {scheme_version}, expected {MPT_KEY_SCHEME_VERSION}"
+        ));
+    }
+    let value = verify_proof_path(root, &mpt_key(dataset_id, chunk_id), proof)?;
+    let payload = String::from_utf8(value)?;
+    let workers = payload.split('|').map(|v| v.to_owned()).collect::<Vec<_>>();
+    if workers.iter().any(|w| w == worker_id) {
+        Ok(workers)
     } else {
         Err(anyhow!("Wrong assignment"))
     }
 }
+
+/// A fully populated assignment trie together with its root hash.
+pub struct CachedTrie {
+    pub trie: EthTrie<MemoryDB>,
+    pub root: Vec<u8>,
+    pub metrics: AssignmentLoadMetrics,
+    last_used: u64,
+}
+
+/// Bounded in-memory cache of built assignment tries keyed by assignment id,
+/// so evidence rows sharing an assignment reuse the same trie.  The least
+/// recently used trie is dropped once `capacity` is exceeded.
+pub struct AssignmentTrieCache {
+    capacity: usize,
+    max_assignment_bytes: u64,
+    tick: u64,
+    entries: HashMap<String, CachedTrie>,
+}
+
+impl AssignmentTrieCache {
+    pub fn new(capacity: usize, max_assignment_bytes: u64) -> Self {
+        AssignmentTrieCache {
+            capacity: capacity.max(1),
+            max_assignment_bytes,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Return the trie for `assignment_id`, building it from `location` if it
+    /// is not cached yet.
+    pub async fn get_or_build(
+        &mut self,
+        assignment_id: &str,
+        location: &AssignmentLocation,
+        cache: Option<&AssignmentCache>,
+    ) -> Result<&mut CachedTrie, anyhow::Error> {
+        self.tick += 1;
+        if !self.entries.contains_key(assignment_id) {
+            let mut trie = EthTrie::new(Arc::new(MemoryDB::new(true)));
+            let metrics =
+                populate_trie(location, &mut trie, cache, self.max_assignment_bytes).await?;
+            let root = trie.root_hash()?.to_vec();
+            if self.entries.len() >= self.capacity {
+                if let Some(oldest) = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(id, _)| id.clone())
+                {
+                    self.entries.remove(&oldest);
+                }
+            }
+            self.entries.insert(
+                assignment_id.to_owned(),
+                CachedTrie {
+                    trie,
+                    root,
+                    metrics,
+                    last_used: 0,
+                },
+            );
+        }
+        let entry = self
+            .entries
+            .get_mut(assignment_id)
+            .ok_or(anyhow!("Trie for {assignment_id} is missing"))?;
+        entry.last_used = self.tick;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    /// dataset -> chunk -> workers
+    type Chunks = Vec<(String, String, Vec<String>)>;
+
+    fn random_worker(rng: &mut StdRng) -> String {
+        bs58::encode(rng.r#gen::<[u8; 32]>()).into_string()
+    }
+
+    fn random_chunks(rng: &mut StdRng) -> Chunks {
+        let pool = (0..rng.gen_range(3..20))
+            .map(|_| random_worker(rng))
+            .collect::<Vec<_>>();
+        let mut chunks = Vec::new();
+        for d in 0..rng.gen_range(1..5) {
+            let dataset_id = format!("s3://dataset-{d}-{}", rng.r#gen::<u32>());
+            for _ in 0..rng.gen_range(1..40) {
+                let from = rng.gen_range(0..1_000_000u64);
+                let chunk_id = format!(
+                    "{from:010}/{from:010}-{:010}-{:08x}",
+                    from + 100,
+                    rng.r#gen::<u32>()
+                );
+                let mut workers = (0..rng.gen_range(1..pool.len().min(5) + 1))
+                    .map(|_| pool[rng.gen_range(0..pool.len())].clone())
+                    .collect::<Vec<_>>();
+                workers.sort();
+                workers.dedup();
+                chunks.push((dataset_id.clone(), chunk_id, workers));
+            }
+        }
+        chunks
+    }
+
+    fn populate(chunks: &[(String, String, Vec<String>)]) -> EthTrie<MemoryDB> {
+        let mut trie = EthTrie::new(Arc::new(MemoryDB::new(true)));
+        for (dataset_id, chunk_id, workers) in chunks {
+            insert_chunk(&mut trie, dataset_id, chunk_id, workers).unwrap();
+        }
+        trie
+    }
+
+    /// Run `check` against 32 seeded random assignments, each with its
+    /// populated trie and root.
+    fn for_each_assignment(
+        mut check: impl FnMut(&mut StdRng, &Chunks, &mut EthTrie<MemoryDB>, &[u8]),
+    ) {
+        for seed in 0..32 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let chunks = random_chunks(&mut rng);
+            let mut trie = populate(&chunks);
+            let root = trie.root_hash().unwrap();
+            check(&mut rng, &chunks, &mut trie, root.as_ref());
+        }
+    }
+
+    fn verify(
+        root: &[u8],
+        dataset_id: &str,
+        chunk_id: &str,
+        worker_id: &str,
+        proof: &[Vec<u8>],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        verify_mpt_proof(
+            root,
+            MPT_KEY_SCHEME_VERSION,
+            dataset_id,
+            chunk_id,
+            worker_id,
+            proof,
+        )
+    }
+
+    /// A key that differs from `key` only after the proof prefix.
+    fn prefix_sibling(rng: &mut StdRng, key: &[u8; 32]) -> [u8; 32] {
+        let mut sibling = *key;
+        sibling[MPT_PROOF_KEY_PREFIX_LEN..].copy_from_slice(&rng.r#gen::<[u8; 24]>());
+        sibling
+    }
+
+    #[test]
+    fn proofs_round_trip() {
+        for_each_assignment(|_, chunks, trie, root| {
+            for (dataset_id, chunk_id, workers) in chunks {
+                for worker in workers {
+                    let proof = make_mpt_proof(trie, dataset_id, chunk_id, worker).unwrap();
+                    assert_eq!(
+                        &verify(root, dataset_id, chunk_id, worker, &proof).unwrap(),
+                        workers
+                    );
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn prefix_proof_reaches_full_key_leaf() {
+        for_each_assignment(|_, chunks, trie, _| {
+            for (dataset_id, chunk_id, _) in chunks {
+                let key = mpt_key(dataset_id, chunk_id);
+                assert_eq!(
+                    trie.get_proof(&key[..MPT_PROOF_KEY_PREFIX_LEN]).unwrap(),
+                    trie.get_proof(&key).unwrap()
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn unknown_scheme_version_is_rejected() {
+        for_each_assignment(|_, chunks, trie, root| {
+            let (dataset_id, chunk_id, workers) = &chunks[0];
+            let proof = make_mpt_proof(trie, dataset_id, chunk_id, &workers[0]).unwrap();
+            for version in [0, MPT_KEY_SCHEME_VERSION + 1] {
+                assert!(
+                    verify_mpt_proof(root, version, dataset_id, chunk_id, &workers[0], &proof)
+                        .is_err()
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn wrong_worker_is_rejected() {
+        for_each_assignment(|rng, chunks, trie, root| {
+            for (dataset_id, chunk_id, workers) in chunks {
+                let outsider = random_worker(rng);
+                assert!(make_mpt_proof(trie, dataset_id, chunk_id, &outsider).is_err());
+                let proof = make_mpt_proof(trie, dataset_id, chunk_id, &workers[0]).unwrap();
+                assert!(verify(root, dataset_id, chunk_id, &outsider, &proof).is_err());
+            }
+        });
+    }
+
+    #[test]
+    fn tampered_proof_is_rejected() {
+        for_each_assignment(|rng, chunks, trie, root| {
+            for (dataset_id, chunk_id, workers) in chunks {
+                let mut proof = make_mpt_proof(trie, dataset_id, chunk_id, &workers[0]).unwrap();
+                let node = rng.gen_range(0..proof.len());
+                let byte = rng.gen_range(0..proof[node].len());
+                proof[node][byte] ^= 1 << rng.gen_range(0..8);
+                assert!(verify(root, dataset_id, chunk_id, &workers[0], &proof).is_err());
+            }
+        });
+    }
+
+    #[test]
+    fn proof_of_another_chunk_is_rejected() {
+        for_each_assignment(|_, chunks, trie, root| {
+            for pair in chunks.windows(2) {
+                let (dataset_id, chunk_id, workers) = &pair[1];
+                let proof = make_mpt_proof(trie, dataset_id, chunk_id, &workers[0]).unwrap();
+                let (other_dataset, other_chunk, _) = &pair[0];
+                assert!(verify(root, other_dataset, other_chunk, &workers[0], &proof).is_err());
+            }
+        });
+    }
+
+    #[test]
+    fn key_sharing_the_proof_prefix_is_rejected() {
+        for_each_assignment(|rng, chunks, trie, _| {
+            let (dataset_id, chunk_id, workers) = &chunks[0];
+            let key = mpt_key(dataset_id, chunk_id);
+            let sibling = prefix_sibling(rng, &key);
+            let intruder = random_worker(rng);
+            trie.insert(&sibling, &mpt_value(&[intruder.clone()]))
+                .unwrap();
+            let root = trie.root_hash().unwrap();
+            let root: &[u8] = root.as_ref();
+
+            // The prefix no longer identifies a single leaf, so no proof
+            // for the real key can be built from it.
+            assert!(make_mpt_proof(trie, dataset_id, chunk_id, &workers[0]).is_err());
+            // The full-key path of the sibling does not prove the real key.
+            let sibling_proof = trie.get_proof(&sibling).unwrap();
+            assert_eq!(
+                verify_proof_path(root, &sibling, &sibling_proof).unwrap(),
+                mpt_value(&[intruder.clone()])
+            );
+            assert!(verify(root, dataset_id, chunk_id, &intruder, &sibling_proof).is_err());
+            // The real key stays provable with its full path.
+            let proof = trie.get_proof(&key).unwrap();
+            assert_eq!(
+                verify(root, dataset_id, chunk_id, &workers[0], &proof).unwrap(),
+                *workers
+            );
+        });
+    }
+
+    #[test]
+    fn prefix_collision_with_other_workers_is_rejected() {
+        for seed in 0..32 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let chunks = random_chunks(&mut rng);
+            // The chunk is absent and a different full key holds its proof
+            // prefix, assigned to another worker set that still includes the
+            // claimed worker.
+            let (dataset_id, chunk_id, workers) = &chunks[0];
+            let mut trie = populate(&chunks[1..]);
+            let key = mpt_key(dataset_id, chunk_id);
+            let sibling = prefix_sibling(&mut rng, &key);
+            let colliding = vec![workers[0].clone(), random_worker(&mut rng)];
+            assert_ne!(&colliding, workers);
+            trie.insert(&sibling, &mpt_value(&colliding)).unwrap();
+            let root = trie.root_hash().unwrap();
+
+            // Requesting by prefix routes to the colliding leaf...
+            let proof = trie.get_proof(&key[..MPT_PROOF_KEY_PREFIX_LEN]).unwrap();
+            assert_eq!(proof, trie.get_proof(&sibling).unwrap());
+            // ...whose payload names the worker, yet the full key rejects it.
+            assert!(verify(root.as_ref(), dataset_id, chunk_id, &workers[0], &proof).is_err());
+            assert!(make_mpt_proof(&mut trie, dataset_id, chunk_id, &workers[0]).is_err());
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::types::Proof;
+use crate::{
+    mpt::MPT_KEY_SCHEME_VERSION,
+    types::{Proof, PublicationStatus},
+};
 use std::collections::HashMap;
 
 /// In-memory store for ZK proofs, keyed by `query_id`.
@@ -20,7 +23,9 @@ impl ProofStorage {
             Proof {
                 proof_bytes,
                 public_values,
-                is_published: false,
+                publication: PublicationStatus::Unpublished,
+                fee_wei: None,
+                mpt_key_scheme_version: Some(MPT_KEY_SCHEME_VERSION),
             },
         );
     }
@@ -28,7 +33,7 @@ impl ProofStorage {
     /// Mark an existing proof as published.  Returns `true` if the entry existed.
     pub fn mark_published(&mut self, query_id: &str) -> bool {
         if let Some(proof) = self.proofs.get_mut(query_id) {
-            proof.is_published = true;
+            proof.publication = PublicationStatus::Final;
             true
         } else {
             false
@@ -38,15 +43,44 @@ impl ProofStorage {
     /// If the proof already exists mark it published; otherwise insert a
     /// placeholder entry (empty proof / public-values) with `is_published = true`.
     pub fn upsert_published(&mut self, query_id: String) {
-        if !self.mark_published(&query_id) {
-            self.proofs.insert(
-                query_id,
-                Proof {
-                    proof_bytes: vec![],
-                    public_values: vec![],
-                    is_published: true,
-                },
-            );
+        self.upsert_publication(query_id, PublicationStatus::Final);
+    }
+
+    /// Set the publication status, inserting a placeholder entry if the proof
+    /// is unknown.  A final publication is never downgraded to pending.
+    pub fn upsert_publication(&mut self, query_id: String, status: PublicationStatus) {
+        let proof = self.proofs.entry(query_id).or_insert_with(|| Proof {
+            proof_bytes: vec![],
+            public_values: vec![],
+            publication: PublicationStatus::Unpublished,
+            fee_wei: None,
+            mpt_key_scheme_version: None,
+        });
+        if !(proof.publication == PublicationStatus::Final && status == PublicationStatus::Pending) {
+            proof.publication = status;
+        }
+    }
+
+    /// Undo a publication whose block was reorged out.  Placeholder entries
+    /// created only for the publication are removed.
+    pub fn rollback_publication(&mut self, query_id: &str) {
+        if let Some(proof) = self.proofs.get_mut(query_id) {
+            if proof.proof_bytes.is_empty() && proof.public_values.is_empty() {
+                self.proofs.remove(query_id);
+            } else {
+                proof.publication = PublicationStatus::Unpublished;
+            }
+        }
+    }
+
+    /// Record the fee paid for submitting a proof.  Returns `true` if the
+    /// entry existed.
+    pub fn record_fee(&mut self, query_id: &str, fee_wei: u128) -> bool {
+        if let Some(proof) = self.proofs.get_mut(query_id) {
+            proof.fee_wei = Some(fee_wei.to_string());
+            true
+        } else {
+            false
         }
     }
 
@@ -59,7 +93,7 @@ impl ProofStorage {
     pub fn list_published(&self) -> Vec<String> {
         self.proofs
             .iter()
-            .filter(|(_, p)| p.is_published)
+            .filter(|(_, p)| p.is_published())
             .map(|(id, _)| id.clone())
             .collect()
     }
@@ -0,0 +1,306 @@
+//! Resolution of `FraudFound` events `(peer_id, timestamp)` to the `query_id`
+//! they refer to.
+//!
+//! The emitted timestamp is the client timestamp of the query, but it may
+//! have lost precision on its way, so queries of the worker are matched within
+//! a tolerance window.  Among several candidates a query we built a proof for
+//! wins, then the one closest in time; remaining ties are reported as
+//! ambiguous.  Lookups are batched into one ClickHouse query per time window,
+//! and unresolved events are retried with exponential backoff, since
+//! `worker_query_logs` may lag behind the chain.
+
+use crate::{db::get_queries_by_workers_and_ts, proof_storage::ProofStorage};
+use clickhouse::Client;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Lookups further apart than this are split into separate queries.
+const MAX_BATCH_SPAN_MS: u64 = 3_600_000;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(60);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(3600);
+/// Unresolved events are given up after this many retries.
+const MAX_RETRIES: u32 = 24;
+
+/// Store key of a `FraudFound` record: (block hash, log index).
+pub type FraudEventKey = (String, u64);
+
+pub struct FraudLookup {
+    pub key: FraudEventKey,
+    pub worker_id: String,
+    pub ts_ms: u64,
+}
+
+/// Delay before the next lookup after `attempts` failed ones: doubling from
+/// `RETRY_BASE_DELAY`, capped at `RETRY_MAX_DELAY`.
+fn retry_delay(attempts: u32) -> Duration {
+    RETRY_BASE_DELAY
+        .saturating_mul(1 << attempts.min(16))
+        .min(RETRY_MAX_DELAY)
+}
+
+struct RetryState {
+    attempts: u32,
+    next_at: Instant,
+}
+
+pub struct QueryIdResolver {
+    tolerance_ms: u64,
+    slack_sec: u64,
+    retries: HashMap<FraudEventKey, RetryState>,
+}
+
+impl QueryIdResolver {
+    pub fn new(tolerance_ms: u64, slack_sec: u64) -> Self {
+        QueryIdResolver {
+            tolerance_ms,
+            slack_sec,
+            retries: HashMap::new(),
+        }
+    }
+
+    /// Resolve every lookup to a `query_id`, or to the reason it could not be.
+    pub async fn resolve(
+        &self,
+        client: &Client,
+        lookups: &[FraudLookup],
+        proof_storage: &Mutex<ProofStorage>,
+    ) -> HashMap<FraudEventKey, Result<String, String>> {
+        let mut sorted = lookups.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|l| l.ts_ms);
+        let mut batches = Vec::<Vec<&FraudLookup>>::new();
+        for lookup in sorted {
+            match batches.last_mut() {
+                Some(batch) if lookup.ts_ms - batch[0].ts_ms <= MAX_BATCH_SPAN_MS => {
+                    batch.push(lookup)
+                }
+                _ => batches.push(vec![lookup]),
+            }
+        }
+
+        let mut resolved = HashMap::new();
+        for batch in batches {
+            let workers = batch
+                .iter()
+                .map(|l| l.worker_id.clone())
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let from_ms = batch[0].ts_ms.saturating_sub(self.tolerance_ms);
+            let to_ms = batch[batch.len() - 1].ts_ms + self.tolerance_ms;
+            match get_queries_by_workers_and_ts(client, &workers, from_ms, to_ms, self.slack_sec)
+                .await
+            {
+                Ok(rows) => {
+                    for lookup in batch {
+                        let candidates = rows
+                            .iter()
+                            .filter(|row| row.worker_id == lookup.worker_id)
+                            .filter_map(|row| {
+                                let distance = row.client_timestamp_ms.abs_diff(lookup.ts_ms as i64);
+                                (distance <= self.tolerance_ms)
+                                    .then(|| (row.query_id.as_str(), distance))
+                            })
+                            .collect::<Vec<_>>();
+                        resolved.insert(
+                            lookup.key.clone(),
+                            self.pick(&candidates, proof_storage),
+                        );
+                    }
+                }
+                Err(err) => {
+                    for lookup in batch {
+                        resolved.insert(lookup.key.clone(), Err(format!("clickhouse error: {err}")));
+                    }
+                }
+            }
+        }
+        resolved
+    }
+
+    /// Choose among `(query_id, distance_ms)` candidates.
+    fn pick(
+        &self,
+        candidates: &[(&str, u64)],
+        proof_storage: &Mutex<ProofStorage>,
+    ) -> Result<String, String> {
+        let mut best = HashMap::<&str, u64>::new();
+        for (query_id, distance) in candidates {
+            let entry = best.entry(query_id).or_insert(*distance);
+            *entry = (*entry).min(*distance);
+        }
+        if best.is_empty() {
+            return Err(format!(
+                "no query of this worker within {} ms",
+                self.tolerance_ms
+            ));
+        }
+        let with_evidence = {
+            let storage = proof_storage.lock().unwrap();
+            best.iter()
+                .filter(|(query_id, _)| storage.exists(query_id))
+                .map(|(query_id, distance)| (*query_id, *distance))
+                .collect::<Vec<_>>()
+        };
+        let pool = if with_evidence.is_empty() {
+            best.into_iter().collect::<Vec<_>>()
+        } else {
+            with_evidence
+        };
+        let nearest = pool.iter().map(|(_, distance)| *distance).min().unwrap_or_default();
+        let closest = pool
+            .iter()
+            .filter(|(_, distance)| *distance == nearest)
+            .collect::<Vec<_>>();
+        match closest.as_slice() {
+            [(query_id, _)] => Ok(query_id.to_string()),
+            _ => Err(format!(
+                "ambiguous: {} queries {nearest} ms away",
+                closest.len()
+            )),
+        }
+    }
+
+    /// Whether an unresolved event should be looked up again now.
+    pub fn is_due(&self, key: &FraudEventKey) -> bool {
+        self.retries
+            .get(key)
+            .is_none_or(|retry| retry.attempts < MAX_RETRIES && retry.next_at <= Instant::now())
+    }
+
+    /// Schedule the next attempt for an unresolved event.
+    pub fn schedule_retry(&mut self, key: FraudEventKey) {
+        let retry = self.retries.entry(key.clone()).or_insert(RetryState {
+            attempts: 0,
+            next_at: Instant::now(),
+        });
+        retry.attempts += 1;
+        retry.next_at = Instant::now() + retry_delay(retry.attempts);
+        if retry.attempts == MAX_RETRIES {
+            warn!(
+                "query resolver: giving up on FraudFound {}:{} after {MAX_RETRIES} attempts",
+                key.0, key.1
+            );
+        }
+    }
+
+    pub fn resolved(&mut self, key: &FraudEventKey) {
+        self.retries.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage_with(query_ids: &[&str]) -> Mutex<ProofStorage> {
+        let mut storage = ProofStorage::new();
+        for query_id in query_ids {
+            storage.add_proof(query_id.to_string(), vec![1], vec![2]);
+        }
+        Mutex::new(storage)
+    }
+
+    /// (name, `(query_id, distance_ms)` candidates, ids with a stored proof,
+    /// expected pick)
+    type PickCase = (
+        &'static str,
+        &'static [(&'static str, u64)],
+        &'static [&'static str],
+        Result<&'static str, &'static str>,
+    );
+
+    #[test]
+    fn pick_breaks_ties_within_the_window() {
+        let resolver = QueryIdResolver::new(1000, 60);
+        let cases: &[PickCase] = &[
+            (
+                "no candidate",
+                &[],
+                &[],
+                Err("no query of this worker within 1000 ms"),
+            ),
+            ("single", &[("a", 700)], &[], Ok("a")),
+            (
+                "closest wins",
+                &[("a", 300), ("b", 20), ("c", 999)],
+                &[],
+                Ok("b"),
+            ),
+            (
+                "repeated id keeps its best distance",
+                &[("a", 900), ("b", 50), ("a", 10)],
+                &[],
+                Ok("a"),
+            ),
+            (
+                "equal distances are ambiguous",
+                &[("a", 40), ("b", 40), ("c", 90)],
+                &[],
+                Err("ambiguous: 2 queries 40 ms away"),
+            ),
+            (
+                "evidence beats distance",
+                &[("a", 0), ("b", 800)],
+                &["b"],
+                Ok("b"),
+            ),
+            (
+                "closest with evidence wins",
+                &[("a", 0), ("b", 800), ("c", 500)],
+                &["b", "c"],
+                Ok("c"),
+            ),
+            (
+                "evidence tie is ambiguous",
+                &[("a", 0), ("b", 500), ("c", 500)],
+                &["b", "c"],
+                Err("ambiguous: 2 queries 500 ms away"),
+            ),
+        ];
+        for (name, candidates, with_evidence, expected) in cases {
+            let picked = resolver.pick(candidates, &storage_with(with_evidence));
+            let expected = expected.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(picked, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY * 2);
+        assert_eq!(retry_delay(2), RETRY_BASE_DELAY * 4);
+        assert_eq!(retry_delay(5), RETRY_BASE_DELAY * 32);
+        for attempts in 6..=MAX_RETRIES + 10 {
+            assert_eq!(retry_delay(attempts), RETRY_MAX_DELAY, "attempt {attempts}");
+        }
+        assert!((1..64).all(|a| retry_delay(a) >= retry_delay(a - 1)));
+    }
+
+    #[test]
+    fn retries_are_delayed_and_given_up() {
+        let mut resolver = QueryIdResolver::new(1000, 60);
+        let key = ("0xabc".to_owned(), 3);
+        assert!(resolver.is_due(&key));
+        resolver.schedule_retry(key.clone());
+        assert!(!resolver.is_due(&key));
+        let retry = &resolver.retries[&key];
+        assert_eq!(retry.attempts, 1);
+        assert!(retry.next_at > Instant::now() + RETRY_BASE_DELAY);
+
+        // Due again once the delay has passed, until the last attempt.
+        resolver.retries.get_mut(&key).unwrap().next_at = Instant::now();
+        assert!(resolver.is_due(&key));
+        for _ in 1..MAX_RETRIES {
+            resolver.schedule_retry(key.clone());
+        }
+        assert!(resolver.retries[&key].next_at <= Instant::now() + RETRY_MAX_DELAY);
+        resolver.retries.get_mut(&key).unwrap().next_at = Instant::now();
+        assert!(!resolver.is_due(&key));
+
+        resolver.resolved(&key);
+        assert!(resolver.is_due(&key));
+    }
+}
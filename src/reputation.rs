@@ -0,0 +1,307 @@
+//! Per-worker reputation.
+//!
+//! Workers are persisted as JSON lines (see [`crate::store`]).  Changes are
+//! only marked in memory; [`crate::loops::reputation`] periodically takes a
+//! [`ReputationSnapshot`] and writes it outside the store lock.
+//!
+//! Besides the counters and the capped history, each worker keeps the keys of
+//! the events counted within [`DEDUP_WINDOW_SECS`], so that re-scanning the
+//! discovery window does not count an event again after it dropped out of
+//! the history.
+
+use crate::{
+    store::{read_jsonl, write_jsonl},
+    types::{ReputationEvent, ReputationEventKind, WorkerReputation, WorkerSummary},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum number of history events kept per worker.
+const MAX_HISTORY_PER_WORKER: usize = 200;
+
+/// How long the key of a counted event is kept for deduplication.  Longer
+/// than the 30-day discovery window, so a query cannot be re-scanned after
+/// its key expired.
+pub const DEDUP_WINDOW_SECS: u64 = 31 * 24 * 3600;
+
+/// Dedup key of an event: its kind and `query_id`, or `detail` when there is
+/// no `query_id`.
+type EventKey = (ReputationEventKind, String);
+
+/// Persisted form of a worker.
+#[derive(Serialize, Deserialize)]
+struct StoredWorker {
+    #[serde(flatten)]
+    reputation: WorkerReputation,
+    /// Keys of the events counted within the dedup window, with the time
+    /// they were counted.
+    seen: Vec<(EventKey, u64)>,
+}
+
+/// Content of the store at some version, to be written without holding the
+/// store lock.
+pub struct ReputationSnapshot {
+    path: PathBuf,
+    version: u64,
+    workers: Vec<StoredWorker>,
+}
+
+impl ReputationSnapshot {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn write(&self) -> Result<(), anyhow::Error> {
+        write_jsonl(&self.path, &self.workers)
+    }
+}
+
+/// Reputation store, keyed by worker `PeerId` (base58 string).
+pub struct ReputationStore {
+    path: Option<PathBuf>,
+    pub workers: HashMap<String, WorkerReputation>,
+    /// worker -> event key -> unix time it was counted.
+    seen: HashMap<String, HashMap<EventKey, u64>>,
+    /// Bumped on every change.
+    version: u64,
+    /// Version last written to `path`.
+    persisted: u64,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+impl WorkerReputation {
+    fn new(worker_id: String) -> Self {
+        WorkerReputation {
+            worker_id,
+            divergences: 0,
+            proofs: 0,
+            fraud_found: 0,
+            cleared: 0,
+            availability_failures: 0,
+            history: Vec::new(),
+        }
+    }
+
+    /// Score in `[0, 100]`; starts at 100 and decreases with every recorded
+    /// misbehaviour, on-chain confirmations weighing the most.  Cleared
+    /// accusations are informational and do not count.
+    pub fn score(&self) -> f64 {
+        let penalty = self.divergences as f64 * 2.0
+            + self.proofs as f64 * 10.0
+            + self.fraud_found as f64 * 25.0
+            + (self.availability_failures as f64).sqrt();
+        (100.0 - penalty).clamp(0.0, 100.0)
+    }
+
+    pub fn summary(&self) -> WorkerSummary {
+        WorkerSummary {
+            worker_id: self.worker_id.clone(),
+            divergences: self.divergences,
+            proofs: self.proofs,
+            fraud_found: self.fraud_found,
+            cleared: self.cleared,
+            availability_failures: self.availability_failures,
+            score: self.score(),
+        }
+    }
+}
+
+impl ReputationStore {
+    /// In-memory store without persistence.
+    pub fn new() -> Self {
+        ReputationStore {
+            path: None,
+            workers: HashMap::new(),
+            seen: HashMap::new(),
+            version: 0,
+            persisted: 0,
+        }
+    }
+
+    /// Load (or create) a store persisted at `path`.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let mut workers = HashMap::new();
+        let mut seen = HashMap::new();
+        for stored in read_jsonl::<StoredWorker>(&path)? {
+            let worker_id = stored.reputation.worker_id.clone();
+            seen.insert(worker_id.clone(), stored.seen.into_iter().collect());
+            workers.insert(worker_id, stored.reputation);
+        }
+        Ok(ReputationStore {
+            path: Some(path),
+            workers,
+            seen,
+            version: 0,
+            persisted: 0,
+        })
+    }
+
+    /// Snapshot of the store if it changed since the last write, or `None`
+    /// when there is nothing to write.  Dedup keys older than
+    /// [`DEDUP_WINDOW_SECS`] are dropped first.
+    pub fn snapshot(&mut self) -> Option<ReputationSnapshot> {
+        let path = self.path.clone()?;
+        if self.version == self.persisted {
+            return None;
+        }
+        let horizon = now_secs().saturating_sub(DEDUP_WINDOW_SECS);
+        for keys in self.seen.values_mut() {
+            keys.retain(|_, counted_at| *counted_at >= horizon);
+        }
+        let mut worker_ids = self.workers.keys().collect::<Vec<_>>();
+        worker_ids.sort();
+        let workers = worker_ids
+            .into_iter()
+            .map(|worker_id| StoredWorker {
+                reputation: self.workers[worker_id].clone(),
+                seen: self
+                    .seen
+                    .get(worker_id)
+                    .map(|keys| keys.iter().map(|(key, ts)| (key.clone(), *ts)).collect())
+                    .unwrap_or_default(),
+            })
+            .collect();
+        Some(ReputationSnapshot {
+            path,
+            version: self.version,
+            workers,
+        })
+    }
+
+    /// Note that the snapshot taken at `version` was written.
+    pub fn mark_persisted(&mut self, version: u64) {
+        self.persisted = self.persisted.max(version);
+    }
+
+    /// Record an event for `worker_id`.  Events with the same kind and
+    /// `query_id` (or `detail`, when there is no `query_id`) are only counted
+    /// once, so re-scanning the same window or re-fetching historical on-chain
+    /// events does not inflate the counters.  Returns `false` for duplicates.
+    pub fn record(
+        &mut self,
+        worker_id: &str,
+        kind: ReputationEventKind,
+        query_id: Option<String>,
+        detail: impl Into<String>,
+    ) -> bool {
+        let worker = self
+            .workers
+            .entry(worker_id.to_owned())
+            .or_insert_with(|| WorkerReputation::new(worker_id.to_owned()));
+        let detail = detail.into();
+        let key = (kind, query_id.clone().unwrap_or_else(|| detail.clone()));
+        let now = now_secs();
+        let seen = self.seen.entry(worker_id.to_owned()).or_default();
+        if seen.contains_key(&key) {
+            return false;
+        }
+        seen.insert(key, now);
+        match kind {
+            ReputationEventKind::Divergence => worker.divergences += 1,
+            ReputationEventKind::Proven => worker.proofs += 1,
+            ReputationEventKind::FraudFound => worker.fraud_found += 1,
+            ReputationEventKind::Cleared => worker.cleared += 1,
+        }
+        worker.history.push(ReputationEvent {
+            kind,
+            query_id,
+            detail,
+            ts: now,
+        });
+        if worker.history.len() > MAX_HISTORY_PER_WORKER {
+            worker.history.remove(0);
+        }
+        self.version += 1;
+        true
+    }
+
+    /// Set the number of failed (non-`ok`) responses of `worker_id` within the
+    /// current discovery window.
+    pub fn set_availability_failures(&mut self, worker_id: &str, failures: u64) {
+        let worker = self
+            .workers
+            .entry(worker_id.to_owned())
+            .or_insert_with(|| WorkerReputation::new(worker_id.to_owned()));
+        if worker.availability_failures != failures {
+            worker.availability_failures = failures;
+            self.version += 1;
+        }
+    }
+
+    /// Summaries of all known workers, worst score first.
+    pub fn list(&self) -> Vec<WorkerSummary> {
+        let mut res = self
+            .workers
+            .values()
+            .map(WorkerReputation::summary)
+            .collect::<Vec<_>>();
+        res.sort_by(|a, b| a.score.total_cmp(&b.score));
+        res
+    }
+
+    pub fn get(&self, worker_id: &str) -> Option<WorkerReputation> {
+        self.workers.get(worker_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> ReputationStore {
+        let path = std::env::temp_dir().join(format!("snoopy-reputation-{}.jsonl", uuid::Uuid::new_v4()));
+        ReputationStore::open(path).unwrap()
+    }
+
+    #[test]
+    fn duplicate_events_are_counted_once() {
+        let mut store = store();
+        let query = || Some("q1".to_owned());
+        assert!(store.record("w", ReputationEventKind::Divergence, query(), "first"));
+        assert!(!store.record("w", ReputationEventKind::Divergence, query(), "again"));
+        assert!(store.record("w", ReputationEventKind::Proven, query(), "proof"));
+        let worker = store.get("w").unwrap();
+        assert_eq!((worker.divergences, worker.proofs), (1, 1));
+        assert_eq!(worker.history.len(), 2);
+    }
+
+    #[test]
+    fn snapshots_are_taken_only_after_changes() {
+        let mut store = store();
+        assert!(store.snapshot().is_none());
+        store.record("w", ReputationEventKind::Divergence, Some("q1".to_owned()), "");
+        let snapshot = store.snapshot().unwrap();
+        snapshot.write().unwrap();
+        store.mark_persisted(snapshot.version());
+        assert!(store.snapshot().is_none());
+
+        let reopened = ReputationStore::open(&snapshot.path).unwrap();
+        assert_eq!(reopened.get("w").unwrap().divergences, 1);
+        assert_eq!(reopened.seen["w"].len(), 1);
+        std::fs::remove_file(&snapshot.path).unwrap();
+    }
+
+    #[test]
+    fn expired_dedup_keys_are_dropped() {
+        let mut store = store();
+        store.record("w", ReputationEventKind::Divergence, Some("old".to_owned()), "");
+        store.record("w", ReputationEventKind::Divergence, Some("new".to_owned()), "");
+        let old = (ReputationEventKind::Divergence, "old".to_owned());
+        *store.seen.get_mut("w").unwrap().get_mut(&old).unwrap() =
+            now_secs() - DEDUP_WINDOW_SECS - 1;
+        store.snapshot().unwrap();
+        assert!(!store.seen["w"].contains_key(&old));
+        assert_eq!(store.seen["w"].len(), 1);
+    }
+}
@@ -1,10 +1,17 @@
 //! Rocket HTTP route handlers.
 
 use crate::{
+    collusion::CollusionThresholds,
     state::InternalState,
-    types::{DiscoveryLoopProgress, Metadata, ProofEntry},
+    types::{
+        CollusionReport, DiscoveryLoopProgress, FraudEventRecord, LatencyReport, Metadata,
+        ProofEntry, TransactionPage, WorkerLatencyStats, WorkerReputation, WorkerSummary,
+    },
 };
+use alloy::primitives::Address;
+use libp2p_identity::PeerId;
 use rocket::{State, get, serde::json::Json, fs::NamedFile};
+use std::str::FromStr;
 
 // ---------------------------------------------------------------------------
 // Route handlers
@@ -20,7 +27,10 @@ pub async fn get_all_proofs(state: &State<InternalState>) -> Json<Vec<ProofEntry
             query_id: query_id.clone(),
             proof_bytes: proof.proof_bytes.clone(),
             public_values: proof.public_values.clone(),
-            is_published: proof.is_published,
+            is_published: proof.is_published(),
+            publication: proof.publication,
+            fee_wei: proof.fee_wei.clone(),
+            mpt_key_scheme_version: proof.mpt_key_scheme_version,
         })
         .collect();
     Json(entries)
@@ -61,3 +71,73 @@ pub async fn get_discovery_progress(
     let progress = state.discovery_progress.lock().unwrap();
     Json(progress.clone())
 }
+
+#[get("/latency")]
+pub async fn get_latency_report(state: &State<InternalState>) -> Json<LatencyReport> {
+    let report = state.latency_report.lock().unwrap();
+    Json(report.clone())
+}
+
+#[get("/latency/<worker_id>")]
+pub async fn get_worker_latency(
+    state: &State<InternalState>,
+    worker_id: &str,
+) -> Option<Json<WorkerLatencyStats>> {
+    let report = state.latency_report.lock().unwrap();
+    report
+        .workers
+        .iter()
+        .find(|w| w.worker_id == worker_id)
+        .cloned()
+        .map(Json)
+}
+
+#[get("/collusion")]
+pub async fn get_collusion_report(state: &State<InternalState>) -> Json<CollusionReport> {
+    let graph = state.agreement_graph.lock().unwrap();
+    Json(graph.report(CollusionThresholds::from_args(&state.config)))
+}
+
+#[get("/workers")]
+pub async fn get_workers(state: &State<InternalState>) -> Json<Vec<WorkerSummary>> {
+    let reputation = state.reputation.lock().unwrap();
+    Json(reputation.list())
+}
+
+#[get("/workers/<peer_id>")]
+pub async fn get_worker(
+    state: &State<InternalState>,
+    peer_id: &str,
+) -> Option<Json<WorkerReputation>> {
+    PeerId::from_str(peer_id).ok()?;
+    let reputation = state.reputation.lock().unwrap();
+    reputation.get(peer_id).map(Json)
+}
+
+/// Indexed `FraudFound` events, newest first.
+#[get("/fraud-events?<peer_id>&<limit>")]
+pub async fn get_fraud_events(
+    state: &State<InternalState>,
+    peer_id: Option<&str>,
+    limit: Option<usize>,
+) -> Json<Vec<FraudEventRecord>> {
+    let events = state.fraud_events.lock().unwrap();
+    Json(events.list(peer_id, limit.unwrap_or(100)))
+}
+
+/// `verifyAndEmit` transactions sent by `sender`, newest first.
+#[get("/transactions?<sender>&<offset>&<limit>")]
+pub async fn get_transactions(
+    state: &State<InternalState>,
+    sender: &str,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Option<Json<TransactionPage>> {
+    let sender = Address::from_str(sender).ok()?;
+    let transactions = state.transactions.lock().unwrap();
+    Some(Json(transactions.page(
+        &sender.to_string(),
+        offset.unwrap_or(0),
+        limit.unwrap_or(50).min(500),
+    )))
+}
@@ -0,0 +1,194 @@
+//! Shared RPC provider pool used by all contract helpers.
+//!
+//! Endpoints are tried in the configured order (`--rpc-url` first, then
+//! `--rpc-fallback-urls`).  A provider is connected lazily, kept while it is
+//! healthy and replaced by the next reachable endpoint when it fails.  The
+//! health loop pings the active provider and moves back to the primary
+//! endpoint once it is reachable again.  Subscriptions need a WebSocket, so
+//! they get their own provider restricted to `ws(s)://` endpoints.
+
+use crate::types::Args;
+use alloy::providers::{DynProvider, Provider, ProviderBuilder};
+use anyhow::anyhow;
+use std::{future::Future, time::Duration};
+use tokio::{sync::Mutex, time::timeout};
+use tracing::{info, warn};
+
+#[derive(Default)]
+struct SlotState {
+    /// (endpoint index, provider)
+    active: Option<(usize, DynProvider)>,
+    /// Endpoint to try first on the next connect.
+    next_start: usize,
+}
+
+struct Slot {
+    ws_only: bool,
+    state: Mutex<SlotState>,
+}
+
+impl Slot {
+    fn new(ws_only: bool) -> Self {
+        Slot {
+            ws_only,
+            state: Mutex::new(SlotState::default()),
+        }
+    }
+}
+
+pub struct RpcPool {
+    urls: Vec<String>,
+    request_timeout: Duration,
+    any: Slot,
+    ws: Slot,
+}
+
+fn is_ws(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+impl RpcPool {
+    pub fn new(urls: Vec<String>, request_timeout: Duration) -> Self {
+        RpcPool {
+            urls,
+            request_timeout,
+            any: Slot::new(false),
+            ws: Slot::new(true),
+        }
+    }
+
+    pub fn from_args(args: &Args) -> Self {
+        let mut urls = vec![args.rpc_url.clone()];
+        for url in &args.rpc_fallback_urls {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        RpcPool::new(urls, Duration::from_secs(args.rpc_timeout))
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    /// Connect to `urls[index]` and make sure it answers within the timeout.
+    async fn connect(&self, index: usize) -> Result<DynProvider, anyhow::Error> {
+        let url = &self.urls[index];
+        let provider = timeout(self.request_timeout, ProviderBuilder::new().connect(url))
+            .await
+            .map_err(|_| anyhow!("timed out connecting to {url}"))??
+            .erased();
+        timeout(self.request_timeout, provider.get_block_number())
+            .await
+            .map_err(|_| anyhow!("{url} did not answer within {:?}", self.request_timeout))??;
+        Ok(provider)
+    }
+
+    async fn slot_provider(&self, slot: &Slot) -> Result<DynProvider, anyhow::Error> {
+        let mut state = slot.state.lock().await;
+        if let Some((_, provider)) = &state.active {
+            return Ok(provider.clone());
+        }
+        let n = self.urls.len();
+        let mut last_err = anyhow!("no RPC endpoint configured");
+        for offset in 0..n {
+            let index = (state.next_start + offset) % n;
+            if slot.ws_only && !is_ws(&self.urls[index]) {
+                continue;
+            }
+            match self.connect(index).await {
+                Ok(provider) => {
+                    info!("rpc: connected to {}", self.urls[index]);
+                    state.active = Some((index, provider.clone()));
+                    return Ok(provider);
+                }
+                Err(err) => {
+                    warn!("rpc: {} unavailable: {err:?}", self.urls[index]);
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn reset_slot(&self, slot: &Slot) {
+        let mut state = slot.state.lock().await;
+        if let Some((index, _)) = state.active.take() {
+            warn!("rpc: dropping connection to {}", self.urls[index]);
+            state.next_start = (index + 1) % self.urls.len();
+        }
+    }
+
+    /// Healthy provider on any endpoint.
+    pub async fn provider(&self) -> Result<DynProvider, anyhow::Error> {
+        self.slot_provider(&self.any).await
+    }
+
+    /// Healthy provider on a WebSocket endpoint, for subscriptions.
+    pub async fn ws_provider(&self) -> Result<DynProvider, anyhow::Error> {
+        self.slot_provider(&self.ws).await
+    }
+
+    /// Drop the current provider so the next call fails over.
+    pub async fn reset(&self) {
+        self.reset_slot(&self.any).await
+    }
+
+    /// Drop the current subscription provider.
+    pub async fn reset_ws(&self) {
+        self.reset_slot(&self.ws).await
+    }
+
+    /// Run a read-only request with the request timeout, failing over to the
+    /// next endpoint on error.  Not for transactions: a failed attempt is
+    /// retried elsewhere.
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T, anyhow::Error>
+    where
+        F: Fn(DynProvider) -> Fut,
+        Fut: Future<Output = Result<T, anyhow::Error>>,
+    {
+        let mut last_err = anyhow!("no RPC endpoint configured");
+        for _ in 0..self.urls.len().max(1) {
+            let provider = self.provider().await?;
+            match timeout(self.request_timeout, f(provider)).await {
+                Ok(Ok(value)) => return Ok(value),
+                Ok(Err(err)) => last_err = err,
+                Err(_) => last_err = anyhow!("RPC request timed out after {:?}", self.request_timeout),
+            }
+            warn!("rpc: request failed: {last_err:?}");
+            self.reset().await;
+        }
+        Err(last_err)
+    }
+
+    async fn check_slot(&self, slot: &Slot) {
+        let mut state = slot.state.lock().await;
+        let Some((index, provider)) = state.active.clone() else {
+            return;
+        };
+        let healthy = matches!(
+            timeout(self.request_timeout, provider.get_block_number()).await,
+            Ok(Ok(_))
+        );
+        if !healthy {
+            warn!("rpc: health check failed for {}", self.urls[index]);
+            state.active = None;
+            state.next_start = (index + 1) % self.urls.len();
+            return;
+        }
+        if index != 0 && !(slot.ws_only && !is_ws(&self.urls[0])) {
+            if let Ok(primary) = self.connect(0).await {
+                info!("rpc: primary endpoint {} is back", self.urls[0]);
+                state.active = Some((0, primary));
+                state.next_start = 0;
+            }
+        }
+    }
+
+    /// Ping the active providers, dropping unhealthy ones and moving back to
+    /// the primary endpoint when possible.
+    pub async fn check_health(&self) {
+        self.check_slot(&self.any).await;
+        self.check_slot(&self.ws).await;
+    }
+}
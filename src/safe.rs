@@ -0,0 +1,399 @@
+//! Safe multisig proposals for proof submissions.
+//!
+//! When the submitter is a Safe, proofs cannot be sent with a local key.
+//! Instead each proof becomes a `verifyAndEmit` call wrapped in a Safe
+//! transaction, written as JSON to a directory and/or posted to the Safe
+//! transaction service, where the owners confirm and execute it.
+//!
+//! Proposals are queued behind each other: the nonce is the highest of the
+//! Safe's on-chain nonce, the next free nonce in the service queue and the
+//! next one after this process's previous proposal.
+
+use crate::{
+    admin::signer_from_args,
+    contracts::ProvingManager,
+    rpc::RpcPool,
+    store::write_json,
+    types::{Args, SafeProposal},
+};
+use alloy::{
+    hex,
+    primitives::{Address, B256, Bytes, Signature, U256},
+    providers::Provider,
+    signers::{Signer, local::PrivateKeySigner},
+    sol,
+    sol_types::{SolCall, SolStruct, eip712_domain},
+};
+use anyhow::anyhow;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tracing::info;
+
+sol! {
+    #[sol(rpc)]
+    interface ISafe {
+        function nonce() external view returns (uint256);
+    }
+
+    struct SafeTx {
+        address to;
+        uint256 value;
+        bytes data;
+        uint8 operation;
+        uint256 safeTxGas;
+        uint256 baseGas;
+        uint256 gasPrice;
+        address gasToken;
+        address refundReceiver;
+        uint256 nonce;
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct QueuedPage {
+    results: Vec<QueuedTransaction>,
+}
+
+#[derive(serde::Deserialize)]
+struct QueuedTransaction {
+    nonce: u64,
+}
+
+/// Next nonce after the queued transactions of `page`, which lists those
+/// from nonce `from` on, highest first.
+fn next_after_queue(page: &QueuedPage, from: u64) -> u64 {
+    page.results.first().map_or(from, |tx| tx.nonce + 1)
+}
+
+/// Nonce of the next proposal: the highest of the one after this process's
+/// previous proposal, the Safe's on-chain nonce and the next free nonce in
+/// the service queue.
+fn select_nonce(local_next: u64, onchain: u64, queued: u64) -> u64 {
+    local_next.max(onchain).max(queued)
+}
+
+/// Safe transaction calling `to` with `calldata` and no value, refund or
+/// gas settings.
+fn safe_tx(to: Address, calldata: Vec<u8>, nonce: u64) -> SafeTx {
+    SafeTx {
+        to,
+        value: U256::ZERO,
+        data: Bytes::from(calldata),
+        operation: 0,
+        safeTxGas: U256::ZERO,
+        baseGas: U256::ZERO,
+        gasPrice: U256::ZERO,
+        gasToken: Address::ZERO,
+        refundReceiver: Address::ZERO,
+        nonce: U256::from(nonce),
+    }
+}
+
+/// EIP-712 hash of `tx` for `safe` on `chain_id`, as signed by the owners.
+fn safe_tx_hash(tx: &SafeTx, chain_id: u64, safe: Address) -> B256 {
+    let domain = eip712_domain! {
+        chain_id: chain_id,
+        verifying_contract: safe,
+    };
+    tx.eip712_signing_hash(&domain)
+}
+
+/// `r || s || v` with `v` in {27, 28}, as the transaction service expects.
+fn signature_hex(signature: &Signature) -> String {
+    format!("0x{}", hex::encode(signature.as_bytes()))
+}
+
+pub struct SafeProposer {
+    rpc: Arc<RpcPool>,
+    safe: Address,
+    /// Owner or delegate signing the proposals; without it proposals are
+    /// written unsigned.
+    signer: Option<PrivateKeySigner>,
+    dir: Option<PathBuf>,
+    service_url: Option<String>,
+    http: reqwest::Client,
+    next_nonce: Mutex<u64>,
+}
+
+impl SafeProposer {
+    pub fn from_args(rpc: Arc<RpcPool>, args: &Args) -> Result<Self, anyhow::Error> {
+        let safe = args
+            .safe_address
+            .ok_or(anyhow!("--safe-address is required in safe submission mode"))?;
+        if args.safe_proposal_dir.is_none() && args.safe_tx_service_url.is_none() {
+            return Err(anyhow!(
+                "--safe-proposal-dir or --safe-tx-service-url is required in safe submission mode"
+            ));
+        }
+        let signer = match (&args.private_key, &args.safe_tx_service_url) {
+            (Some(_), _) => Some(signer_from_args(args)?),
+            (None, Some(_)) => {
+                return Err(anyhow!(
+                    "--private-key of a Safe owner or delegate is required to post proposals"
+                ));
+            }
+            (None, None) => None,
+        };
+        if let Some(dir) = &args.safe_proposal_dir {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(SafeProposer {
+            rpc,
+            safe,
+            signer,
+            dir: args.safe_proposal_dir.as_ref().map(PathBuf::from),
+            service_url: args
+                .safe_tx_service_url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_owned()),
+            http: reqwest::Client::new(),
+            next_nonce: Mutex::new(0),
+        })
+    }
+
+    /// Next nonce after the transactions queued in the Safe transaction
+    /// service, if one is configured.
+    async fn service_next_nonce(&self, from: u64) -> Result<u64, anyhow::Error> {
+        let Some(url) = &self.service_url else {
+            return Ok(from);
+        };
+        let page: QueuedPage = self
+            .http
+            .get(format!(
+                "{url}/api/v1/safes/{}/multisig-transactions/",
+                self.safe
+            ))
+            .query(&[
+                ("executed", "false"),
+                ("nonce__gte", &from.to_string()),
+                ("ordering", "-nonce"),
+                ("limit", "1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(next_after_queue(&page, from))
+    }
+
+    /// Build, sign and publish a Safe transaction calling `to` with
+    /// `calldata`.  `label` ends up in the file name and the proposal origin.
+    pub async fn propose(
+        &self,
+        to: Address,
+        calldata: Vec<u8>,
+        label: &str,
+    ) -> Result<SafeProposal, anyhow::Error> {
+        let safe = self.safe;
+        let (chain_id, onchain_nonce) = self
+            .rpc
+            .call(|provider| async move {
+                let chain_id = provider.get_chain_id().await?;
+                let nonce = ISafe::new(safe, provider).nonce().call().await?;
+                Ok((chain_id, nonce.to::<u64>()))
+            })
+            .await?;
+        let queued_nonce = self.service_next_nonce(onchain_nonce).await?;
+        let nonce = {
+            let mut next = self.next_nonce.lock().unwrap();
+            let nonce = select_nonce(*next, onchain_nonce, queued_nonce);
+            *next = nonce + 1;
+            nonce
+        };
+
+        let tx = safe_tx(to, calldata, nonce);
+        let hash = safe_tx_hash(&tx, chain_id, safe);
+        let (sender, signature) = match &self.signer {
+            Some(signer) => {
+                let signature = signer.sign_hash(&hash).await?;
+                (
+                    Some(signer.address().to_string()),
+                    Some(signature_hex(&signature)),
+                )
+            }
+            None => (None, None),
+        };
+        let proposal = SafeProposal {
+            safe: safe.to_string(),
+            to: to.to_string(),
+            value: "0".to_owned(),
+            data: format!("0x{}", hex::encode(&tx.data)),
+            operation: 0,
+            safe_tx_gas: "0".to_owned(),
+            base_gas: "0".to_owned(),
+            gas_price: "0".to_owned(),
+            gas_token: Address::ZERO.to_string(),
+            refund_receiver: Address::ZERO.to_string(),
+            nonce,
+            contract_transaction_hash: hash.to_string(),
+            sender,
+            signature,
+            origin: format!("snoopy: {label}"),
+        };
+
+        if let Err(err) = self.publish(&proposal, label).await {
+            // Hand the nonce to the next proposal instead of leaving a gap.
+            let mut next = self.next_nonce.lock().unwrap();
+            if *next == nonce + 1 {
+                *next = nonce;
+            }
+            return Err(err);
+        }
+        Ok(proposal)
+    }
+
+    async fn publish(&self, proposal: &SafeProposal, label: &str) -> Result<(), anyhow::Error> {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{}-{}.json", proposal.nonce, label));
+            write_json(&path, proposal)?;
+            info!(
+                "safe: wrote proposal {} to {}",
+                proposal.contract_transaction_hash,
+                path.display()
+            );
+        }
+        if let Some(url) = &self.service_url {
+            let response = self
+                .http
+                .post(format!("{url}/api/v1/safes/{}/multisig-transactions/", self.safe))
+                .json(proposal)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(anyhow!(
+                    "Safe transaction service rejected the proposal: {status} {body}"
+                ));
+            }
+            info!(
+                "safe: posted proposal {} (nonce {})",
+                proposal.contract_transaction_hash, proposal.nonce
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Propose a `verifyAndEmit` call for the proof of `query_id`.
+pub async fn propose_proof(
+    proposer: &SafeProposer,
+    query_id: &str,
+    proof_bytes: Vec<u8>,
+    public_values: Vec<u8>,
+    manager_address: Address,
+    config_name: &str,
+) -> Result<SafeProposal, anyhow::Error> {
+    let calldata = ProvingManager::verifyAndEmitCall {
+        config_name: config_name.to_owned(),
+        publicValues: public_values.into(),
+        proofBytes: proof_bytes.into(),
+    }
+    .abi_encode();
+    let label = query_id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_");
+    proposer.propose(manager_address, calldata, &label).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::{
+        primitives::{address, b256, keccak256},
+        signers::SignerSync,
+    };
+    use rocket::serde::json;
+
+    const SAFE: Address = address!("0x5afe00000000000000000000000000000000cafe");
+    const TO: Address = address!("0x1111111111111111111111111111111111111111");
+    const CHAIN_ID: u64 = 421614;
+
+    #[test]
+    fn safe_tx_hash_matches_the_reference_encoding() {
+        // Type hashes of the Safe contracts (SafeTx, and the EIP712Domain of
+        // Safe >= 1.3.0).
+        assert_eq!(
+            SafeTx::eip712_type_hash(&safe_tx(TO, vec![], 0)),
+            b256!("0xbb8310d486368db6bd6f849402fdd73ad53d316b5a4b2644ad6efe0f941286d8")
+        );
+        assert_eq!(
+            keccak256("EIP712Domain(uint256 chainId,address verifyingContract)"),
+            b256!("0x47e79534a245952e8b16893a336b85a3d9ea9fa8c573f3d803afb92a79469218")
+        );
+        // keccak256(0x1901 || domainSeparator || hashStruct(SafeTx)), computed
+        // independently of alloy.
+        let data = vec![0xde, 0xad, 0xbe, 0xef];
+        let hash = safe_tx_hash(&safe_tx(TO, data.clone(), 7), CHAIN_ID, SAFE);
+        assert_eq!(
+            hash,
+            b256!("0x08e1c66c3c9ce7c600d171636deaf9f35cd376aeef988e97947f942b0b504492")
+        );
+        // Chain id and nonce are both bound into the hash.
+        assert_ne!(
+            safe_tx_hash(&safe_tx(TO, data.clone(), 7), CHAIN_ID + 1, SAFE),
+            hash
+        );
+        assert_ne!(safe_tx_hash(&safe_tx(TO, data, 8), CHAIN_ID, SAFE), hash);
+    }
+
+    #[test]
+    fn signature_recovers_the_owner() {
+        // First default anvil account.
+        let signer: PrivateKeySigner =
+            "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcaf784d7bf4f2ff80"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            signer.address(),
+            address!("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266")
+        );
+        let hash = safe_tx_hash(
+            &safe_tx(TO, vec![0xde, 0xad, 0xbe, 0xef], 7),
+            CHAIN_ID,
+            SAFE,
+        );
+        let encoded = signature_hex(&signer.sign_hash_sync(&hash).unwrap());
+        let bytes = hex::decode(&encoded).unwrap();
+        assert_eq!(bytes.len(), 65);
+        assert!(matches!(bytes[64], 27 | 28));
+        let signature = Signature::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(
+            signature.recover_address_from_prehash(&hash).unwrap(),
+            signer.address()
+        );
+    }
+
+    #[test]
+    fn nonce_is_the_highest_of_all_sources() {
+        // (local next, on-chain, next after the service queue, expected)
+        let cases = [
+            (0, 0, 0, 0),
+            (0, 5, 5, 5),
+            (0, 5, 8, 8),
+            (9, 5, 8, 9),
+            (3, 5, 5, 5),
+            (6, 5, 5, 6),
+            (6, 7, 7, 7),
+        ];
+        for (local, onchain, queued, expected) in cases {
+            assert_eq!(
+                select_nonce(local, onchain, queued),
+                expected,
+                "{local} {onchain} {queued}"
+            );
+        }
+    }
+
+    #[test]
+    fn queue_is_read_from_the_service_page() {
+        let empty: QueuedPage = json::from_str(r#"{"count": 0, "results": []}"#).unwrap();
+        assert_eq!(next_after_queue(&empty, 5), 5);
+        let page: QueuedPage =
+            json::from_str(r#"{"count": 3, "results": [{"nonce": 9, "safeTxHash": "0x01"}]}"#)
+                .unwrap();
+        assert_eq!(next_after_queue(&page, 5), 10);
+    }
+}
@@ -1,9 +1,31 @@
-use crate::{proof_storage::ProofStorage, types::{Args, DiscoveryLoopProgress}};
+use crate::{
+    assignment_cache::AssignmentCache,
+    collusion::AgreementGraph,
+    fraud_events::FraudEventStore,
+    proof_storage::ProofStorage,
+    reputation::ReputationStore,
+    rpc::RpcPool,
+    safe::SafeProposer,
+    submission::Submitter,
+    transactions::TransactionStore,
+    types::{Args, DiscoveryLoopProgress, LatencyReport},
+};
 use std::sync::{Arc, Mutex};
 
 /// Rocket-managed shared state.
 pub struct InternalState {
     pub proof_storage: Arc<Mutex<ProofStorage>>,
     pub discovery_progress: Arc<Mutex<DiscoveryLoopProgress>>,
+    pub latency_report: Arc<Mutex<LatencyReport>>,
+    pub agreement_graph: Arc<Mutex<AgreementGraph>>,
+    pub reputation: Arc<Mutex<ReputationStore>>,
+    pub assignment_cache: Arc<AssignmentCache>,
+    pub rpc: Arc<RpcPool>,
+    pub fraud_events: Arc<Mutex<FraudEventStore>>,
+    pub transactions: Arc<Mutex<TransactionStore>>,
+    /// Set in `direct` submission mode.
+    pub submitter: Option<Arc<Submitter>>,
+    /// Set in `safe` submission mode.
+    pub safe_proposer: Option<Arc<SafeProposer>>,
     pub config: Args,
 }
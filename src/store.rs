@@ -0,0 +1,76 @@
+//! File persistence shared by the on-disk stores.
+//!
+//! Stores keep their records in memory and rewrite the whole file on change:
+//! the new content goes to `<path>.tmp` first and is renamed over `<path>`, so
+//! a crash never leaves a truncated file behind.
+
+use rocket::serde::json;
+use serde::{Serialize, de::DeserializeOwned};
+use std::{fs, io, path::Path};
+
+/// Atomically replace the content of `path`.
+pub fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+/// Read a JSON lines file; a missing file reads as empty.
+pub fn read_jsonl<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, anyhow::Error> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(json::from_str(line)?))
+        .collect()
+}
+
+/// Atomically replace `path` with `records` as JSON lines.
+pub fn write_jsonl<'a, T: Serialize + 'a>(
+    path: &Path,
+    records: impl IntoIterator<Item = &'a T>,
+) -> Result<(), anyhow::Error> {
+    let mut content = String::new();
+    for record in records {
+        content.push_str(&json::to_string(record)?);
+        content.push('\n');
+    }
+    Ok(write_atomic(path, content)?)
+}
+
+/// Read a JSON file; a missing file reads as `T::default()`.
+pub fn read_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, anyhow::Error> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    Ok(json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Atomically replace `path` with `value` as JSON.
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), anyhow::Error> {
+    Ok(write_atomic(path, json::to_string(value)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn jsonl_round_trip() {
+        let path = std::env::temp_dir().join(format!("snoopy-store-{}.jsonl", uuid::Uuid::new_v4()));
+        assert!(read_jsonl::<BTreeMap<String, u64>>(&path).unwrap().is_empty());
+        let records = (0..3)
+            .map(|i| BTreeMap::from([(format!("key{i}"), i)]))
+            .collect::<Vec<_>>();
+        write_jsonl(&path, &records).unwrap();
+        assert_eq!(read_jsonl::<BTreeMap<String, u64>>(&path).unwrap(), records);
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        assert!(!Path::new(&tmp).exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
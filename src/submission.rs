@@ -0,0 +1,591 @@
+//! Fee management for proof submissions.
+//!
+//! A submission is sent as an EIP-1559 transaction whose fees are taken from
+//! the node's estimate and clamped to the configured caps, with a gas limit of
+//! the estimate plus a safety margin.  While it stays unmined it is replaced
+//! under the same nonce with fees raised by `fee_bump_percent`, until the fee
+//! cap or the bump limit is reached.  Before every send the worst-case cost
+//! (`gas_limit * maxFeePerGas`) is checked against the daily spending cap; the
+//! fee actually paid is booked once the transaction is mined, and the ledger
+//! is written on the blocking pool.
+
+use crate::{
+    admin::signer_from_args,
+    contracts::post_proof,
+    proof_storage::ProofStorage,
+    rpc::RpcPool,
+    store::{read_json, write_json},
+    types::{Args, PublicationStatus},
+};
+use alloy::{
+    network::{ReceiptResponse, TransactionBuilder},
+    primitives::{Address, B256},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::{TransactionReceipt, TransactionRequest},
+    signers::local::PrivateKeySigner,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug, Clone)]
+pub struct GasSettings {
+    pub max_fee_per_gas: Option<u128>,
+    pub max_priority_fee_per_gas: Option<u128>,
+    pub gas_limit_margin_percent: u64,
+    pub bump_after: Duration,
+    pub bump_percent: u64,
+    pub max_bumps: u32,
+    pub confirmations: u64,
+    pub timeout: Duration,
+}
+
+impl GasSettings {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            max_fee_per_gas: args.max_fee_per_gas,
+            max_priority_fee_per_gas: args.max_priority_fee_per_gas,
+            gas_limit_margin_percent: args.gas_limit_margin_percent,
+            bump_after: Duration::from_secs(args.fee_bump_after),
+            bump_percent: args.fee_bump_percent,
+            max_bumps: args.max_fee_bumps,
+            confirmations: args.submission_confirmations.max(1),
+            timeout: Duration::from_secs(args.submission_timeout),
+        }
+    }
+
+    fn cap_max_fee(&self, fee: u128) -> u128 {
+        self.max_fee_per_gas.map_or(fee, |cap| fee.min(cap))
+    }
+
+    fn cap_priority_fee(&self, fee: u128) -> u128 {
+        self.max_priority_fee_per_gas.map_or(fee, |cap| fee.min(cap))
+    }
+
+    /// Raise `fee` by `bump_percent`, but never by less than the 10% nodes
+    /// require to accept a replacement.
+    fn bump(&self, fee: u128) -> u128 {
+        let percent = self.bump_percent.max(10) as u128;
+        fee + (fee * percent).div_ceil(100).max(1)
+    }
+
+    /// Both fees bumped for a replacement, or the flag of the cap that stops
+    /// the bumping.  Both fees have to rise for the node to accept the
+    /// replacement, so a cap on either one ends it.
+    fn bump_fees(&self, max_fee: u128, priority_fee: u128) -> Result<(u128, u128), &'static str> {
+        let max_fee = self.bump(max_fee);
+        let priority_fee = self.bump(priority_fee);
+        if self.max_fee_per_gas.is_some_and(|cap| max_fee > cap) {
+            return Err("--max-fee-per-gas");
+        }
+        if self
+            .max_priority_fee_per_gas
+            .is_some_and(|cap| priority_fee > cap)
+        {
+            return Err("--max-priority-fee-per-gas");
+        }
+        Ok((max_fee, priority_fee))
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct LedgerState {
+    /// Days since the unix epoch (UTC).
+    day: u64,
+    spent_wei: u128,
+}
+
+/// Fees spent on submissions during the current UTC day.
+pub struct FeeLedger {
+    path: Option<PathBuf>,
+    cap_wei: Option<u128>,
+    state: LedgerState,
+}
+
+/// Days since the unix epoch of a unix time (UTC).
+fn utc_day(unix_secs: u64) -> u64 {
+    unix_secs / SECONDS_PER_DAY
+}
+
+fn today() -> u64 {
+    utc_day(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    )
+}
+
+/// Ledger content to be written without holding the ledger lock.
+pub struct LedgerSnapshot {
+    path: PathBuf,
+    state: LedgerState,
+}
+
+impl LedgerSnapshot {
+    pub fn write(&self) -> Result<(), anyhow::Error> {
+        write_json(&self.path, &self.state)
+    }
+}
+
+impl FeeLedger {
+    /// In-memory ledger without persistence.
+    pub fn new(cap_wei: Option<u128>) -> Self {
+        FeeLedger {
+            path: None,
+            cap_wei,
+            state: LedgerState::default(),
+        }
+    }
+
+    /// Load (or create) a ledger persisted at `path`.
+    pub fn open(path: impl Into<PathBuf>, cap_wei: Option<u128>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let state = read_json(&path)?;
+        Ok(FeeLedger {
+            path: Some(path),
+            cap_wei,
+            state,
+        })
+    }
+
+    fn roll_over(&mut self) {
+        let day = today();
+        if self.state.day != day {
+            self.state = LedgerState { day, spent_wei: 0 };
+        }
+    }
+
+    /// Current content to persist, if the ledger has a file.
+    pub fn snapshot(&self) -> Option<LedgerSnapshot> {
+        Some(LedgerSnapshot {
+            path: self.path.clone()?,
+            state: self.state.clone(),
+        })
+    }
+
+    /// Fees spent today.
+    pub fn spent_today(&mut self) -> u128 {
+        self.roll_over();
+        self.state.spent_wei
+    }
+
+    /// Fail if spending `cost_wei` more today would exceed the cap.
+    pub fn check(&mut self, cost_wei: u128) -> Result<(), anyhow::Error> {
+        let spent = self.spent_today();
+        match self.cap_wei {
+            Some(cap) if spent.saturating_add(cost_wei) > cap => Err(anyhow!(
+                "daily fee cap reached: spent {spent} wei of {cap}, next transaction may cost up to {cost_wei}"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Book a paid fee.  The ledger is not written; see [`Self::snapshot`].
+    pub fn record(&mut self, fee_wei: u128) {
+        self.roll_over();
+        self.state.spent_wei = self.state.spent_wei.saturating_add(fee_wei);
+    }
+}
+
+/// Outcome of a mined and confirmed submission.
+#[derive(Debug, Clone)]
+pub struct SubmissionReceipt {
+    pub tx_hash: B256,
+    pub block_number: u64,
+    pub gas_used: u64,
+    pub fee_wei: u128,
+}
+
+fn paid_fee(receipt: &TransactionReceipt) -> u128 {
+    receipt.gas_used() as u128 * receipt.effective_gas_price()
+}
+
+/// First receipt found among the transactions sent for one nonce.
+async fn find_receipt<P: Provider>(
+    provider: &P,
+    sent: &[B256],
+) -> Result<Option<TransactionReceipt>, anyhow::Error> {
+    for hash in sent {
+        if let Some(receipt) = provider.get_transaction_receipt(*hash).await? {
+            return Ok(Some(receipt));
+        }
+    }
+    Ok(None)
+}
+
+/// Poll until one of `sent` is mined or `until` passes.
+async fn wait_mined<P: Provider>(
+    provider: &P,
+    sent: &[B256],
+    until: Instant,
+) -> Result<Option<TransactionReceipt>, anyhow::Error> {
+    loop {
+        match find_receipt(provider, sent).await {
+            Ok(Some(receipt)) => return Ok(Some(receipt)),
+            Ok(None) => {}
+            Err(err) => warn!("submission: receipt lookup failed: {err:?}"),
+        }
+        if Instant::now() >= until {
+            return Ok(None);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Wait for `confirmations` on top of the mined transaction, following it if
+/// a reorg moves it to another block.
+async fn wait_confirmed<P: Provider>(
+    provider: &P,
+    sent: &[B256],
+    confirmations: u64,
+    deadline: Instant,
+) -> Result<TransactionReceipt, anyhow::Error> {
+    loop {
+        if let Some(receipt) = find_receipt(provider, sent).await? {
+            let block = receipt.block_number().unwrap_or_default();
+            let head = provider.get_block_number().await?;
+            if head + 1 >= block + confirmations {
+                return Ok(receipt);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow!(
+                "submission not confirmed {confirmations} times in time; sent: {sent:?}"
+            ));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Signs and sends proof submissions with managed fees.
+pub struct Submitter {
+    rpc: Arc<RpcPool>,
+    signer: PrivateKeySigner,
+    settings: GasSettings,
+    ledger: Mutex<FeeLedger>,
+    /// Serialises ledger writes, so that an older snapshot never overwrites
+    /// a newer one.
+    ledger_writes: tokio::sync::Mutex<()>,
+}
+
+impl Submitter {
+    pub fn new(
+        rpc: Arc<RpcPool>,
+        signer: PrivateKeySigner,
+        settings: GasSettings,
+        ledger: FeeLedger,
+    ) -> Self {
+        Submitter {
+            rpc,
+            signer,
+            settings,
+            ledger: Mutex::new(ledger),
+            ledger_writes: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Submitter signing with `--private-key` and booking fees in
+    /// `--fee-ledger-file`.
+    pub fn from_args(rpc: Arc<RpcPool>, args: &Args) -> Result<Self, anyhow::Error> {
+        Ok(Self::new(
+            rpc,
+            signer_from_args(args)?,
+            GasSettings::from_args(args),
+            FeeLedger::open(&args.fee_ledger_file, args.daily_fee_cap)?,
+        ))
+    }
+
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    /// Fees spent on submissions today.
+    pub fn spent_today(&self) -> u128 {
+        self.ledger.lock().unwrap().spent_today()
+    }
+
+    /// Book a paid fee and write the ledger on the blocking pool.
+    async fn book(&self, fee_wei: u128) {
+        self.ledger.lock().unwrap().record(fee_wei);
+        let _writing = self.ledger_writes.lock().await;
+        let Some(snapshot) = self.ledger.lock().unwrap().snapshot() else {
+            return;
+        };
+        match tokio::task::spawn_blocking(move || snapshot.write()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("fee ledger: failed to persist: {err:?}"),
+            Err(err) => error!("fee ledger: persist task failed: {err:?}"),
+        }
+    }
+
+    /// Send `calldata` to `to` and wait until it is confirmed.  A mined but
+    /// reverted transaction is booked and reported as an error.
+    pub async fn send(
+        &self,
+        to: Address,
+        calldata: Vec<u8>,
+    ) -> Result<SubmissionReceipt, anyhow::Error> {
+        let settings = &self.settings;
+        let from = self.signer.address();
+        let provider = ProviderBuilder::new()
+            .wallet(self.signer.clone())
+            .connect_provider(self.rpc.provider().await?);
+        let base = TransactionRequest::default()
+            .with_from(from)
+            .with_to(to)
+            .with_input(calldata);
+
+        let estimate = provider
+            .estimate_gas(base.clone())
+            .await
+            .map_err(|err| anyhow!("gas estimation failed, call would revert: {err}"))?;
+        let gas_limit = estimate + estimate * settings.gas_limit_margin_percent / 100;
+        let fees = provider.estimate_eip1559_fees().await?;
+        let mut max_fee = settings.cap_max_fee(fees.max_fee_per_gas);
+        let mut priority_fee = settings
+            .cap_priority_fee(fees.max_priority_fee_per_gas)
+            .min(max_fee);
+        let nonce = provider.get_transaction_count(from).pending().await?;
+        let deadline = Instant::now() + settings.timeout;
+
+        let mut sent = Vec::<B256>::new();
+        let mut mined = None;
+        for attempt in 0..=settings.max_bumps {
+            if attempt > 0 {
+                match settings.bump_fees(max_fee, priority_fee) {
+                    Ok(bumped) => (max_fee, priority_fee) = bumped,
+                    Err(cap) => {
+                        warn!(
+                            "submission: {cap} reached, waiting for nonce {nonce} without replacing it"
+                        );
+                        break;
+                    }
+                }
+            }
+            self.ledger
+                .lock()
+                .unwrap()
+                .check(gas_limit as u128 * max_fee)?;
+            let tx = base
+                .clone()
+                .with_nonce(nonce)
+                .with_gas_limit(gas_limit)
+                .with_max_fee_per_gas(max_fee)
+                .with_max_priority_fee_per_gas(priority_fee);
+            match provider.send_transaction(tx).await {
+                Ok(pending) => {
+                    info!(
+                        "submission: sent {} (nonce {nonce}, maxFeePerGas {max_fee}, maxPriorityFeePerGas {priority_fee}, gas {gas_limit})",
+                        pending.tx_hash()
+                    );
+                    sent.push(*pending.tx_hash());
+                }
+                // An earlier attempt may have been mined meanwhile.
+                Err(err) if !sent.is_empty() => warn!("submission: replacement rejected: {err}"),
+                Err(err) => return Err(err.into()),
+            }
+            let until = deadline.min(Instant::now() + settings.bump_after);
+            mined = wait_mined(&provider, &sent, until).await?;
+            if mined.is_some() || Instant::now() >= deadline {
+                break;
+            }
+        }
+        if mined.is_none() {
+            mined = wait_mined(&provider, &sent, deadline).await?;
+        }
+        let Some(mined) = mined else {
+            return Err(anyhow!(
+                "submission with nonce {nonce} not mined within {}s; sent: {sent:?}",
+                settings.timeout.as_secs()
+            ));
+        };
+
+        let receipt =
+            match wait_confirmed(&provider, &sent, settings.confirmations, deadline).await {
+                Ok(receipt) => receipt,
+                Err(err) => {
+                    // The fee is most likely spent anyway.
+                    self.book(paid_fee(&mined)).await;
+                    return Err(err);
+                }
+            };
+        let fee_wei = paid_fee(&receipt);
+        self.book(fee_wei).await;
+        if !receipt.status() {
+            return Err(anyhow!(
+                "submission {} reverted after spending {fee_wei} wei",
+                receipt.transaction_hash()
+            ));
+        }
+        Ok(SubmissionReceipt {
+            tx_hash: receipt.transaction_hash(),
+            block_number: receipt.block_number().unwrap_or_default(),
+            gas_used: receipt.gas_used(),
+            fee_wei,
+        })
+    }
+}
+
+/// Submit the stored proof of `query_id` and record the fee paid on it.  The
+/// proof is marked pending until the FraudFound indexer finalises it.
+pub async fn publish_proof(
+    submitter: &Submitter,
+    query_id: &str,
+    proof_storage: &Mutex<ProofStorage>,
+    manager_address: Address,
+    config_name: &str,
+) -> Result<SubmissionReceipt, anyhow::Error> {
+    let proof = proof_storage
+        .lock()
+        .unwrap()
+        .proofs
+        .get(query_id)
+        .cloned()
+        .ok_or(anyhow!("No proof stored for {query_id}"))?;
+    if proof.is_published() {
+        return Err(anyhow!("Proof of {query_id} is already published"));
+    }
+    if proof.proof_bytes.is_empty() {
+        return Err(anyhow!("Proof of {query_id} is a placeholder"));
+    }
+    let receipt = post_proof(
+        proof.proof_bytes,
+        proof.public_values,
+        submitter,
+        manager_address,
+        config_name,
+    )
+    .await?;
+    let mut storage = proof_storage.lock().unwrap();
+    storage.record_fee(query_id, receipt.fee_wei);
+    storage.upsert_publication(query_id.to_owned(), PublicationStatus::Pending);
+    info!(
+        "submission: published proof of {query_id} in {} (fee {} wei)",
+        receipt.tx_hash, receipt.fee_wei
+    );
+    Ok(receipt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(
+        bump_percent: u64,
+        max_fee: Option<u128>,
+        max_priority_fee: Option<u128>,
+    ) -> GasSettings {
+        GasSettings {
+            max_fee_per_gas: max_fee,
+            max_priority_fee_per_gas: max_priority_fee,
+            gas_limit_margin_percent: 20,
+            bump_after: Duration::from_secs(60),
+            bump_percent,
+            max_bumps: 5,
+            confirmations: 1,
+            timeout: Duration::from_secs(600),
+        }
+    }
+
+    #[test]
+    fn bump_rounds_up_and_never_stalls() {
+        let s = settings(25, None, None);
+        assert_eq!(s.bump(1000), 1250);
+        assert_eq!(s.bump(1001), 1252);
+        assert_eq!(s.bump(1), 2);
+        assert_eq!(s.bump(0), 1);
+    }
+
+    #[test]
+    fn bump_is_at_least_ten_percent() {
+        for percent in [0, 1, 5, 9, 10] {
+            let s = settings(percent, None, None);
+            assert_eq!(s.bump(1000), 1100, "{percent}%");
+            assert_eq!(s.bump(1001), 1102, "{percent}%");
+        }
+        assert_eq!(settings(11, None, None).bump(1000), 1110);
+    }
+
+    #[test]
+    fn both_fees_rise_by_the_floor() {
+        let s = settings(3, None, None);
+        let (mut max_fee, mut priority_fee) = (30_000_000_000u128, 1_500_000_000u128);
+        for _ in 0..5 {
+            let (bumped_max, bumped_priority) = s.bump_fees(max_fee, priority_fee).unwrap();
+            assert!(bumped_max * 10 >= max_fee * 11);
+            assert!(bumped_priority * 10 >= priority_fee * 11);
+            (max_fee, priority_fee) = (bumped_max, bumped_priority);
+        }
+    }
+
+    #[test]
+    fn either_fee_cap_stops_the_bumping() {
+        let s = settings(10, Some(1100), Some(110));
+        assert_eq!(s.bump_fees(1000, 100), Ok((1100, 110)));
+        assert_eq!(s.bump_fees(1001, 100), Err("--max-fee-per-gas"));
+        assert_eq!(s.bump_fees(1000, 101), Err("--max-priority-fee-per-gas"));
+        assert_eq!(
+            settings(10, None, Some(110)).bump_fees(10_000, 100),
+            Ok((11_000, 110))
+        );
+    }
+
+    #[test]
+    fn days_roll_over_at_utc_midnight() {
+        assert_eq!(utc_day(0), 0);
+        assert_eq!(utc_day(SECONDS_PER_DAY - 1), 0);
+        assert_eq!(utc_day(SECONDS_PER_DAY), 1);
+        // 2024-03-01T00:00:00Z
+        assert_eq!(utc_day(1_709_251_200), 19_783);
+        assert_eq!(utc_day(1_709_251_199), 19_782);
+
+        let mut ledger = FeeLedger::new(Some(1000));
+        ledger.state = LedgerState {
+            day: today() - 1,
+            spent_wei: 1000,
+        };
+        assert!(ledger.check(1).is_ok());
+        assert_eq!(ledger.spent_today(), 0);
+        assert_eq!(ledger.state.day, today());
+    }
+
+    #[test]
+    fn cap_rejects_the_worst_case_cost() {
+        let mut ledger = FeeLedger::new(Some(1000));
+        ledger.record(600);
+        ledger.record(300);
+        assert_eq!(ledger.spent_today(), 900);
+        assert!(ledger.check(100).is_ok());
+        assert!(ledger.check(101).is_err());
+        ledger.record(u128::MAX);
+        assert!(ledger.check(0).is_err());
+
+        let mut uncapped = FeeLedger::new(None);
+        uncapped.record(u128::MAX);
+        assert!(uncapped.check(u128::MAX).is_ok());
+    }
+
+    #[test]
+    fn ledger_is_written_only_through_snapshots() {
+        assert!(FeeLedger::new(None).snapshot().is_none());
+        let path =
+            std::env::temp_dir().join(format!("snoopy-ledger-{}.json", uuid::Uuid::new_v4()));
+        let mut ledger = FeeLedger::open(&path, Some(1000)).unwrap();
+        ledger.record(400);
+        assert!(!path.exists());
+        ledger.snapshot().unwrap().write().unwrap();
+        let mut reopened = FeeLedger::open(&path, Some(1000)).unwrap();
+        assert_eq!(reopened.spent_today(), 400);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
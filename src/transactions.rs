@@ -0,0 +1,79 @@
+//! Store of `verifyAndEmit` transactions sent to the ProvingManager, per
+//! sender, persisted as JSON lines.
+
+use crate::{
+    store::{read_jsonl, write_jsonl},
+    types::{SubmittedTransaction, TransactionPage},
+};
+use std::{collections::HashMap, path::PathBuf};
+use tracing::error;
+
+pub struct TransactionStore {
+    path: Option<PathBuf>,
+    /// tx hash -> transaction
+    transactions: HashMap<String, SubmittedTransaction>,
+}
+
+impl TransactionStore {
+    /// In-memory store without persistence.
+    pub fn new() -> Self {
+        TransactionStore {
+            path: None,
+            transactions: HashMap::new(),
+        }
+    }
+
+    /// Load (or create) a store persisted at `path`.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let transactions = read_jsonl::<SubmittedTransaction>(&path)?
+            .into_iter()
+            .map(|tx| (tx.tx_hash.clone(), tx))
+            .collect();
+        Ok(TransactionStore {
+            path: Some(path),
+            transactions,
+        })
+    }
+
+    fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut txs = self.transactions.values().collect::<Vec<_>>();
+        txs.sort_by_key(|tx| tx.block_number);
+        if let Err(err) = write_jsonl(path, txs) {
+            error!("transactions: failed to persist {}: {err:?}", path.display());
+        }
+    }
+
+    pub fn contains(&self, tx_hash: &str) -> bool {
+        self.transactions.contains_key(tx_hash)
+    }
+
+    /// Insert or replace several transactions, persisting once.
+    pub fn extend(&mut self, txs: Vec<SubmittedTransaction>) {
+        if txs.is_empty() {
+            return;
+        }
+        for tx in txs {
+            self.transactions.insert(tx.tx_hash.clone(), tx);
+        }
+        self.persist();
+    }
+
+    /// Transactions of `sender` (checksummed address), newest first.
+    pub fn page(&self, sender: &str, offset: usize, limit: usize) -> TransactionPage {
+        let mut txs = self
+            .transactions
+            .values()
+            .filter(|tx| tx.sender == sender)
+            .collect::<Vec<_>>();
+        txs.sort_by(|a, b| b.block_number.cmp(&a.block_number).then(a.tx_hash.cmp(&b.tx_hash)));
+        TransactionPage {
+            total: txs.len(),
+            offset,
+            items: txs.into_iter().skip(offset).take(limit).cloned().collect(),
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use alloy::primitives::Address;
-use clap::Parser;
+use alloy::primitives::{Address, FixedBytes};
+use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use sqd_messages::{Query, QueryFinished};
@@ -20,8 +20,10 @@ pub struct Args {
     #[clap(long, env, default_value = "subsqd_adm")]
     pub db_user: String,
 
+    /// Required when running the server; subcommands that do not touch
+    /// ClickHouse work without it.
     #[clap(long, env)]
-    pub db_password: String,
+    pub db_password: Option<String>,
 
     #[clap(long, env, default_value = "300")]
     pub ts_tolerance: u64,
@@ -38,6 +40,20 @@ pub struct Args {
     #[clap(long, env, default_value = "wss://ethereum-sepolia-rpc.publicnode.com")]
     pub rpc_url: String,
 
+    /// Additional RPC endpoints (WS or HTTP), tried in order when `rpc_url`
+    /// is unavailable.
+    #[clap(long, env, value_delimiter = ',')]
+    pub rpc_fallback_urls: Vec<String>,
+
+    /// Timeout in seconds for connecting to an RPC endpoint and for read-only
+    /// requests.
+    #[clap(long, env, default_value = "30")]
+    pub rpc_timeout: u64,
+
+    /// Seconds between two RPC health checks.
+    #[clap(long, env, default_value = "30")]
+    pub rpc_health_interval: u64,
+
     #[clap(
         long,
         env,
@@ -52,15 +68,435 @@ pub struct Args {
     )]
     pub manager_address: Address,
 
+    /// Block the ProvingManager was deployed at; where FraudFound indexing
+    /// starts when no checkpoint exists.  Defaults to the last ~250k blocks.
+    #[clap(long, env)]
+    pub manager_deployment_block: Option<u64>,
+
+    /// File holding the last block fully processed by the FraudFound indexer.
+    #[clap(long, env, default_value = "fraud-checkpoint.txt")]
+    pub fraud_checkpoint_file: String,
+
+    /// File the indexed FraudFound events are persisted to.
+    #[clap(long, env, default_value = "fraud-events.jsonl")]
+    pub fraud_events_file: String,
+
+    /// File worker reputations are persisted to.
+    #[clap(long, env, default_value = "reputation.jsonl")]
+    pub reputation_file: String,
+
+    /// Seconds between two writes of changed worker reputations.
+    #[clap(long, env, default_value = "30")]
+    pub reputation_flush_interval: u64,
+
+    /// File holding the last block scanned for `verifyAndEmit` transactions.
+    #[clap(long, env, default_value = "transactions-checkpoint.txt")]
+    pub transactions_checkpoint_file: String,
+
+    /// File the indexed `verifyAndEmit` transactions are persisted to.
+    #[clap(long, env, default_value = "transactions.jsonl")]
+    pub transactions_file: String,
+
+    /// Seconds between two scans for new `verifyAndEmit` transactions.
+    #[clap(long, env, default_value = "60")]
+    pub transactions_interval: u64,
+
+    /// Confirmations a block needs before its `verifyAndEmit` transactions
+    /// are indexed.
+    #[clap(long, env, default_value = "12")]
+    pub transactions_confirmations: u64,
+
+    /// Confirmations after which a FraudFound event is considered final.
+    #[clap(long, env, default_value = "12")]
+    pub fraud_confirmations: u64,
+
+    /// Maximum distance in milliseconds between a FraudFound timestamp and
+    /// the client timestamp of the query it is matched to.
+    #[clap(long, env, default_value = "1000")]
+    pub fraud_ts_tolerance_ms: u64,
+
     #[clap(long, env, default_value = "std-long")]
     pub config_name: String,
 
+    /// Private key used to sign proof submissions and the transactions of
+    /// the administration subcommands.
+    #[clap(long, env, hide_env_values = true)]
+    pub private_key: Option<String>,
+
+    /// Upper bound (wei) for `maxFeePerGas` of proof submissions, bumps
+    /// included.  Unbounded when unset.
+    #[clap(long, env)]
+    pub max_fee_per_gas: Option<u128>,
+
+    /// Upper bound (wei) for `maxPriorityFeePerGas` of proof submissions.
+    #[clap(long, env)]
+    pub max_priority_fee_per_gas: Option<u128>,
+
+    /// Percentage added to the estimated gas of a proof submission.
+    #[clap(long, env, default_value = "20")]
+    pub gas_limit_margin_percent: u64,
+
+    /// Seconds a proof submission may stay unmined before it is replaced
+    /// with higher fees.
+    #[clap(long, env, default_value = "90")]
+    pub fee_bump_after: u64,
+
+    /// Percentage by which both fees are raised on replacement; values below
+    /// 10 are raised to 10, since nodes reject smaller replacements.
+    #[clap(long, env, default_value = "15")]
+    pub fee_bump_percent: u64,
+
+    /// Maximum number of replacements of a stuck proof submission.
+    #[clap(long, env, default_value = "5")]
+    pub max_fee_bumps: u32,
+
+    /// Confirmations awaited after a proof submission is mined.
+    #[clap(long, env, default_value = "2")]
+    pub submission_confirmations: u64,
+
+    /// Seconds after which a proof submission is given up.
+    #[clap(long, env, default_value = "900")]
+    pub submission_timeout: u64,
+
+    /// Maximum fees (wei) spent on proof submissions per UTC day.  Unbounded
+    /// when unset.
+    #[clap(long, env)]
+    pub daily_fee_cap: Option<u128>,
+
+    /// File keeping the fees spent today, so the cap survives restarts.
+    #[clap(long, env, default_value = "fee-ledger.json")]
+    pub fee_ledger_file: String,
+
+    /// How new proofs reach the chain.
+    #[clap(long, env, value_enum, default_value = "manual")]
+    pub submission_mode: SubmissionMode,
+
+    /// Safe multisig that submits proofs in `safe` mode.
+    #[clap(long, env)]
+    pub safe_address: Option<Address>,
+
+    /// Directory Safe proposals are written to.
+    #[clap(long, env)]
+    pub safe_proposal_dir: Option<String>,
+
+    /// Safe transaction service (e.g.
+    /// `https://safe-transaction-sepolia.safe.global`) proposals are posted
+    /// to.  Requires `--private-key` of a Safe owner or delegate.
+    #[clap(long, env)]
+    pub safe_tx_service_url: Option<String>,
+
     #[clap(long, env, default_value = "prove-query-result-program")]
     pub program_path: String,
 
     /// Skip actual ZK proof creation and generate random proof bytes instead.
     #[clap(long, env, default_value = "false")]
     pub fake_proof: bool,
+
+    /// Seconds between two runs of the worker latency detector.
+    #[clap(long, env, default_value = "900")]
+    pub latency_interval: u64,
+
+    /// Size (seconds) of the look-back window analysed by the latency detector.
+    #[clap(long, env, default_value = "86400")]
+    pub latency_window: u64,
+
+    /// A response is "fast" when its `total_time` is below this fraction of
+    /// the sibling median.
+    #[clap(long, env, default_value = "0.2")]
+    pub latency_fast_ratio: f64,
+
+    /// A worker is "slow" when its median ratio to siblings exceeds this value.
+    #[clap(long, env, default_value = "3.0")]
+    pub latency_slow_ratio: f64,
+
+    /// Minimum number of comparable samples before a worker can be flagged.
+    #[clap(long, env, default_value = "10")]
+    pub latency_min_samples: usize,
+
+    /// Minimum number of distinct workers among the siblings required before
+    /// anyone is accused.
+    #[clap(long, env, default_value = "3")]
+    pub quorum_min_group_size: usize,
+
+    /// Minimum share of distinct workers that must agree on the majority output.
+    #[clap(long, env, default_value = "0.6")]
+    pub quorum_min_majority_ratio: f64,
+
+    /// Minimum number of distinct workers in the majority group.
+    #[clap(long, env, default_value = "2")]
+    pub quorum_min_majority_workers: usize,
+
+    /// What to do when several output hashes share the largest group.
+    #[clap(long, env, value_enum, default_value = "skip")]
+    pub quorum_tie_policy: QuorumTiePolicy,
+
+    /// Minimum number of shared divergent groups before two workers can be
+    /// linked into a collusion cluster.
+    #[clap(long, env, default_value = "5")]
+    pub collusion_min_shared_groups: usize,
+
+    /// Minimum agreement rate between two workers to link them into a cluster.
+    #[clap(long, env, default_value = "0.95")]
+    pub collusion_min_agreement: f64,
+
+    /// Maximum agreement rate of a cluster with the rest of the network.
+    #[clap(long, env, default_value = "0.2")]
+    pub collusion_max_outside_agreement: f64,
+
+    /// Base URL of the assignment metadata server, or a local mirror directory
+    /// with the same `{network}/{assignment_id}.fb.1.gz` layout.
+    #[clap(long, env, default_value = "https://metadata.sqd-datasets.io/assignments")]
+    pub assignment_source: String,
+
+    /// Directory of the on-disk assignment file cache.
+    #[clap(long, env, default_value = "assignment-cache")]
+    pub assignment_cache_dir: String,
+
+    /// Size limit of the assignment file cache in bytes; 0 disables the cache.
+    #[clap(long, env, default_value = "2147483648")]
+    pub assignment_cache_max_bytes: u64,
+
+    /// Upper bound on the decompressed size of a single assignment in bytes.
+    /// The decompressed flatbuffer is held in memory whole rather than
+    /// streamed, so this also bounds the memory used per loaded assignment.
+    #[clap(long, env, default_value = "1073741824")]
+    pub assignment_max_decompressed_bytes: u64,
+
+    /// Seconds around a query timestamp probed for neighbouring assignments,
+    /// to tolerate assignment switches and client clock skew; 0 disables.
+    /// A neighbour is only used if its root is committed for the query
+    /// timestamp, as that is where the contract checks it.
+    #[clap(long, env, default_value = "120")]
+    pub assignment_boundary_window: u64,
+
+    /// Number of built assignment tries kept in memory.
+    #[clap(long, env, default_value = "8")]
+    pub trie_cache_size: usize,
+
+    /// Run a one-off tool instead of the server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Inspect a single assignment file.
+    Assignment {
+        #[command(flatten)]
+        target: AssignmentTarget,
+        #[command(subcommand)]
+        action: AssignmentAction,
+    },
+    /// Compare two assignments.
+    AssignmentDiff {
+        /// Id of the older assignment.
+        #[clap(long, required_unless_present = "old_location")]
+        old_id: Option<String>,
+        /// URL or local file of the older assignment.
+        #[clap(long, conflicts_with = "old_id")]
+        old_location: Option<String>,
+        /// Id of the newer assignment.
+        #[clap(long, required_unless_present = "new_location")]
+        new_id: Option<String>,
+        /// URL or local file of the newer assignment.
+        #[clap(long, conflicts_with = "new_id")]
+        new_location: Option<String>,
+    },
+    /// Inspect the CommitmentHolder contract and commit assignment roots.
+    Commitment {
+        /// Print the target address and calldata instead of sending
+        /// transactions.
+        #[clap(long, global = true)]
+        dry_run: bool,
+        #[command(subcommand)]
+        action: CommitmentAction,
+    },
+    /// Administer the ProvingManager contract.
+    Manager {
+        /// Print the target address and calldata instead of sending
+        /// transactions.
+        #[clap(long, global = true)]
+        dry_run: bool,
+        #[command(subcommand)]
+        action: ManagerAction,
+    },
+}
+
+/// Which assignment to load: an explicit URL / file, or an id resolved
+/// through `--assignment-source` and `--network`.
+#[derive(clap::Args, Debug, Clone)]
+#[group(required = true, multiple = false)]
+pub struct AssignmentTarget {
+    /// Assignment URL or path to a local `.fb.1.gz` file.
+    #[clap(long)]
+    pub location: Option<String>,
+
+    /// Assignment id.
+    #[clap(long)]
+    pub id: Option<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AssignmentAction {
+    /// Print the MPT root of the assignment.
+    Root,
+    /// List workers assigned to a chunk.
+    Workers {
+        #[clap(long)]
+        dataset: String,
+        #[clap(long)]
+        chunk: String,
+    },
+    /// List chunks (`dataset|chunk`) assigned to a worker.
+    Chunks {
+        #[clap(long)]
+        worker: String,
+    },
+    /// Print the hex MPT proof that a worker is assigned to a chunk.
+    Proof {
+        #[clap(long)]
+        dataset: String,
+        #[clap(long)]
+        chunk: String,
+        #[clap(long)]
+        worker: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CommitmentAction {
+    /// List the assignments committed over a time range.
+    List {
+        /// Start of the range (unix seconds).
+        #[clap(long)]
+        from: u64,
+        /// End of the range (unix seconds); defaults to now.
+        #[clap(long)]
+        to: Option<u64>,
+        /// Probe interval in seconds; shorter-lived assignments may be missed.
+        #[clap(long, default_value = "3600")]
+        step: u64,
+    },
+    /// Show the assignment id and commitment valid at a timestamp.
+    Show {
+        /// Unix seconds; defaults to now.
+        #[clap(long)]
+        timestamp: Option<u64>,
+    },
+    /// Commit the MPT root of an assignment (test deployments).
+    Commit {
+        /// Assignment id stored with the commitment.
+        #[clap(long)]
+        id: String,
+        /// URL or local file of the assignment; resolved from `--id` when
+        /// omitted.
+        #[clap(long)]
+        location: Option<String>,
+        /// Unix seconds from which the commitment is valid; defaults to now.
+        #[clap(long)]
+        timestamp: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ManagerAction {
+    /// Print the verification key of the local program (`--program-path`).
+    Vkey,
+    /// Show a proof configuration.
+    ShowConfig { name: String },
+    /// Add a proof configuration.
+    AddConfig {
+        name: String,
+        /// Number of samples a proof must contain.
+        #[clap(long)]
+        samples: u64,
+        /// Verification key; computed from the local program when omitted.
+        #[clap(long)]
+        vkey: Option<FixedBytes<32>>,
+    },
+    /// Remove a proof configuration.
+    RemoveConfig { name: String },
+    /// Check whether an account holds a role.
+    HasRole {
+        account: Address,
+        #[clap(long, value_enum, default_value = "configurator")]
+        role: ManagerRole,
+    },
+    /// Grant a role to an account.
+    GrantRole {
+        account: Address,
+        #[clap(long, value_enum, default_value = "configurator")]
+        role: ManagerRole,
+    },
+    /// Revoke a role from an account.
+    RevokeRole {
+        account: Address,
+        #[clap(long, value_enum, default_value = "configurator")]
+        role: ManagerRole,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManagerRole {
+    /// `CONFIGURATOR_ROLE`: may add and remove configurations.
+    Configurator,
+    /// `DEFAULT_ADMIN_ROLE`: may grant and revoke roles.
+    Admin,
+}
+
+// ---------------------------------------------------------------------------
+// Quorum rules for odd-one-out detection
+// ---------------------------------------------------------------------------
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuorumTiePolicy {
+    /// Accuse nobody when the largest group is not unique.
+    Skip,
+    /// Keep the tied largest groups and accuse only strictly smaller ones.
+    MinoritiesOnly,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionMode {
+    /// Proofs are published from the web UI with the browser wallet.
+    Manual,
+    /// Every new proof is sent with `--private-key`, with managed fees.
+    Direct,
+    /// Every new proof becomes a `verifyAndEmit` proposal for the Safe
+    /// multisig at `--safe-address`.
+    Safe,
+}
+
+/// Rules deciding when a divergent group of siblings is strong enough
+/// evidence to accuse the minority.
+#[derive(Debug, Clone, Copy)]
+pub struct QuorumRules {
+    pub min_group_size: usize,
+    pub min_majority_ratio: f64,
+    pub min_majority_workers: usize,
+    pub tie_policy: QuorumTiePolicy,
+}
+
+impl QuorumRules {
+    pub fn from_args(args: &Args) -> Self {
+        Self {
+            min_group_size: args.quorum_min_group_size,
+            min_majority_ratio: args.quorum_min_majority_ratio,
+            min_majority_workers: args.quorum_min_majority_workers,
+            tie_policy: args.quorum_tie_policy,
+        }
+    }
+}
+
+/// A query accused of returning a wrong result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OddQuery {
+    pub query_id: String,
+    pub worker_id: String,
+    /// Confidence in `[0, 1]` that this query diverges from the honest result.
+    pub confidence: f64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -73,11 +509,34 @@ pub struct Metadata {
     pub config_name: String,
 }
 
+/// On-chain publication state of a proof, driven by `FraudFound` events.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicationStatus {
+    Unpublished,
+    /// Seen in a block that does not have enough confirmations yet.
+    Pending,
+    /// Confirmed; the block can no longer be reorged out.
+    Final,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Proof {
     pub proof_bytes: Vec<u8>,
     pub public_values: Vec<u8>,
-    pub is_published: bool,
+    pub publication: PublicationStatus,
+    /// Fee (wei) paid by our own submission of this proof.
+    pub fee_wei: Option<String>,
+    /// MPT key scheme the assignment proof inside was built with; unset for
+    /// placeholders of proofs published by others.
+    pub mpt_key_scheme_version: Option<u8>,
+}
+
+impl Proof {
+    /// Published or about to be; either way it must not be submitted again.
+    pub fn is_published(&self) -> bool {
+        self.publication != PublicationStatus::Unpublished
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -86,6 +545,9 @@ pub struct ProofEntry {
     pub proof_bytes: Vec<u8>,
     pub public_values: Vec<u8>,
     pub is_published: bool,
+    pub publication: PublicationStatus,
+    pub fee_wei: Option<String>,
+    pub mpt_key_scheme_version: Option<u8>,
 }
 
 // ---------------------------------------------------------------------------
@@ -112,6 +574,13 @@ pub struct QueryIdRow {
     pub query_id: String,
 }
 
+#[derive(clickhouse::Row, serde::Deserialize, Debug, Clone)]
+pub struct QueryTimestampRow {
+    pub query_id: String,
+    pub worker_id: String,
+    pub client_timestamp_ms: i64,
+}
+
 #[derive(Debug, Clone, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 pub enum QueryResult {
@@ -158,6 +627,19 @@ pub struct QueryExecutedRow {
     pub request_id: String,
 }
 
+/// `portal_logs` timing joined with the query identity from
+/// `worker_query_logs`, used by the latency detector.
+#[derive(clickhouse::Row, Debug, Clone, Serialize, Deserialize)]
+pub struct QueryTimingRow {
+    pub query_id: String,
+    pub worker_id: String,
+    pub hash: String,
+    pub dataset: String,
+    pub chunk_id: String,
+    pub total_time: u32,
+    pub collector_timestamp: u64,
+}
+
 #[derive(clickhouse::Row, Debug, Clone, Serialize, Deserialize)]
 pub struct SignatureRow {
     pub query_id: String,
@@ -167,6 +649,73 @@ pub struct SignatureRow {
     pub result_hash: Vec<u8>,
 }
 
+// ---------------------------------------------------------------------------
+// Assignment loading
+// ---------------------------------------------------------------------------
+
+/// How an assignment candidate was found relative to the query timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssignmentMatch {
+    /// Assignment active at the client timestamp.
+    AtTimestamp,
+    /// Assignment active `assignment_boundary_window` seconds earlier.
+    Previous,
+    /// Assignment active `assignment_boundary_window` seconds later.
+    Next,
+}
+
+/// An assignment a query may have been served under.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssignmentCandidate {
+    pub assignment_id: String,
+    /// Timestamp (seconds) at which the contract returned this id.
+    pub probe_timestamp_sec: u64,
+    pub kind: AssignmentMatch,
+}
+
+/// Memory and time spent loading a single assignment into a trie.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssignmentLoadMetrics {
+    pub location: String,
+    pub from_cache: bool,
+    pub compressed_bytes: u64,
+    /// Size of the decompressed flatbuffer, i.e. the peak buffer held in memory.
+    pub decompressed_bytes: u64,
+    pub chunks: u64,
+    /// Download (or cache read), decompression and flatbuffer validation
+    /// time.
+    pub load_ms: u64,
+    /// Trie construction time.
+    pub build_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkReassignment {
+    pub chunk: String,
+    pub added_workers: Vec<String>,
+    pub removed_workers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetDiff {
+    pub dataset: String,
+    pub added_chunks: Vec<String>,
+    pub removed_chunks: Vec<String>,
+    /// Chunks present in both assignments whose worker set changed.
+    pub reassigned: Vec<ChunkReassignment>,
+}
+
+/// Result of comparing two assignments; only datasets with changes are listed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssignmentDiff {
+    pub old_root: Vec<u8>,
+    pub new_root: Vec<u8>,
+    pub added_workers: Vec<String>,
+    pub removed_workers: Vec<String>,
+    pub datasets: Vec<DatasetDiff>,
+}
+
 // ---------------------------------------------------------------------------
 // ZK proof input data
 // ---------------------------------------------------------------------------
@@ -239,3 +788,209 @@ impl Default for DiscoveryLoopProgress {
         }
     }
 }
+
+// ---------------------------------------------------------------------------
+// Worker latency detector
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyVerdict {
+    Normal,
+    /// Answers implausibly fast compared to siblings (cached or fabricated results).
+    SuspiciouslyFast,
+    /// Consistently slower than siblings.
+    ConsistentlySlow,
+}
+
+/// Latency distribution of a single worker relative to its siblings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerLatencyStats {
+    pub worker_id: String,
+    /// Number of responses that had at least one sibling to compare against.
+    pub samples: usize,
+    /// Responses below `latency_fast_ratio` of the sibling median.
+    pub fast_samples: usize,
+    /// Responses above `latency_slow_ratio` of the sibling median.
+    pub slow_samples: usize,
+    pub p10_total_time: u32,
+    pub p50_total_time: u32,
+    pub p90_total_time: u32,
+    /// Median of `total_time / sibling median` over all samples.
+    pub median_ratio: f64,
+    pub verdict: LatencyVerdict,
+}
+
+/// Result of the latest latency detector run, served via `/latency`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencyReport {
+    /// Unix timestamp (seconds) of when the report was generated; 0 = never.
+    pub generated_at: u64,
+    pub range_start_sec: u32,
+    pub range_end_sec: u32,
+    /// Number of `(query hash, dataset, chunk)` groups with 2+ workers.
+    pub groups: usize,
+    pub workers: Vec<WorkerLatencyStats>,
+}
+
+// ---------------------------------------------------------------------------
+// Collusion analysis
+// ---------------------------------------------------------------------------
+
+/// A group of workers that agree with each other against the rest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollusionCluster {
+    pub workers: Vec<String>,
+    /// Sum of divergent groups shared by pairs of cluster members.
+    pub shared_groups: usize,
+    pub internal_agreement: f64,
+    pub external_agreement: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollusionReport {
+    pub groups_recorded: usize,
+    pub workers_observed: usize,
+    pub clusters: Vec<CollusionCluster>,
+}
+
+// ---------------------------------------------------------------------------
+// Worker reputation
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReputationEventKind {
+    /// Worker was in the minority of a divergent sibling group.
+    Divergence,
+    /// A ZK proof was built against one of the worker's queries.
+    Proven,
+    /// A `FraudFound` event for the worker was seen on-chain.
+    FraudFound,
+    /// An accusation against the worker was dropped (e.g. colluding majority);
+    /// recorded instead of the `Divergence`.
+    Cleared,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReputationEvent {
+    pub kind: ReputationEventKind,
+    pub query_id: Option<String>,
+    pub detail: String,
+    /// Unix timestamp (seconds) when the event was recorded.
+    pub ts: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerReputation {
+    pub worker_id: String,
+    pub divergences: u64,
+    pub proofs: u64,
+    pub fraud_found: u64,
+    pub cleared: u64,
+    /// Failed (non-`ok`) responses within the current discovery window.
+    pub availability_failures: u64,
+    /// Most recent events, oldest first.
+    pub history: Vec<ReputationEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerSummary {
+    pub worker_id: String,
+    pub divergences: u64,
+    pub proofs: u64,
+    pub fraud_found: u64,
+    pub cleared: u64,
+    pub availability_failures: u64,
+    pub score: f64,
+}
+
+#[derive(clickhouse::Row, serde::Deserialize)]
+pub struct WorkerFailuresRow {
+    pub worker_id: String,
+    pub failures: u64,
+}
+
+// ---------------------------------------------------------------------------
+// FraudFound events
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FraudEventStatus {
+    /// Not enough confirmations yet.
+    Pending,
+    Final,
+    /// The block holding the event was reorged out.
+    Reorged,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FraudEventRecord {
+    pub peer_id: String,
+    /// Timestamp of the disputed query, as emitted (milliseconds).
+    pub timestamp_ms: u64,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub tx_hash: String,
+    pub log_index: u64,
+    /// Account that submitted the `verifyAndEmit` transaction, if known.
+    pub sender: Option<String>,
+    pub query_id: Option<String>,
+    /// Why `query_id` could not be resolved.
+    pub resolution_error: Option<String>,
+    pub status: FraudEventStatus,
+}
+
+// ---------------------------------------------------------------------------
+// Submitted transactions
+// ---------------------------------------------------------------------------
+
+/// A `verifyAndEmit` transaction sent to the ProvingManager.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubmittedTransaction {
+    pub tx_hash: String,
+    pub sender: String,
+    pub block_number: u64,
+    pub block_timestamp: Option<u64>,
+    pub config_name: String,
+    pub success: bool,
+    pub gas_used: u64,
+    /// Total fee paid, in wei (decimal string).
+    pub fee_wei: String,
+    /// Number of `FraudFound` events emitted by the transaction.
+    pub fraud_found: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransactionPage {
+    pub total: usize,
+    pub offset: usize,
+    pub items: Vec<SubmittedTransaction>,
+}
+
+/// Multisig transaction proposal in the format accepted by the Safe
+/// transaction service (`POST /api/v1/safes/<safe>/multisig-transactions/`).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeProposal {
+    pub safe: String,
+    pub to: String,
+    pub value: String,
+    /// ABI-encoded `verifyAndEmit` call, 0x-prefixed hex.
+    pub data: String,
+    pub operation: u8,
+    pub safe_tx_gas: String,
+    pub base_gas: String,
+    pub gas_price: String,
+    pub gas_token: String,
+    pub refund_receiver: String,
+    pub nonce: u64,
+    /// EIP-712 hash of the Safe transaction, signed by `sender`.
+    pub contract_transaction_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    pub origin: String,
+}
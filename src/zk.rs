@@ -1,4 +1,4 @@
-//! SP1 ZK proof generation: `build_zk_proof` and `make_proof_data`.
+//! SP1 ZK proof generation: `build_zk_proof`, `make_proof_data` and `compute_vkey`.
 
 use crate::types::{PrivateProofData, QueryExecutedRow};
 use alloy::hex;
@@ -41,6 +41,16 @@ pub async fn build_zk_proof(
     Ok((proof_bytes, public_values))
 }
 
+/// Verification key of the program at `program_path`, as registered in the
+/// ProvingManager configurations.
+pub fn compute_vkey(program_path: &str) -> Result<String, anyhow::Error> {
+    let mut elf = Vec::new();
+    File::open(program_path)?.read_to_end(&mut elf)?;
+    let prover_client = ProverClient::builder().cpu().build();
+    let (_, vk) = prover_client.setup(&elf);
+    Ok(vk.bytes32())
+}
+
 pub fn make_proof_data(
     row: &QueryExecutedRow,
     result_hash: &[u8],
@@ -198,118 +198,40 @@ class WalletManager {
             }
 
             console.log('Loading transactions for wallet:', this.account);
-            console.log('Manager address:', metadata.manager_address);
 
-            // Convert wss:// to https:// if needed
-            let httpRpcUrl = metadata.rpc_url;
-            if (httpRpcUrl.startsWith('wss://')) {
-                httpRpcUrl = httpRpcUrl.replace('wss://', 'https://');
-            }
-
-            // Get the latest block number to limit our search
-            const latestBlockResponse = await fetch(httpRpcUrl, {
-                method: 'POST',
-                headers: {
-                    'Content-Type': 'application/json',
-                },
-                body: JSON.stringify({
-                    jsonrpc: '2.0',
-                    method: 'eth_blockNumber',
-                    params: [],
-                    id: 1
-                })
-            });
-
-            const latestBlockResult = await latestBlockResponse.json();
-            const latestBlock = latestBlockResult.result ? parseInt(latestBlockResult.result, 16) : 0;
-            
-            // Search in the last 10,000 blocks to avoid hitting limits
-            const fromBlock = Math.max(0, latestBlock - 10000);
-            const fromBlockHex = '0x' + fromBlock.toString(16);
-            const toBlock = 'latest';
-
-            console.log('Searching blocks:', fromBlock, 'to', toBlock);
-
-            // Query for all transaction logs from the manager contract
-            const logParams = {
-                fromBlock: fromBlockHex,
-                toBlock: toBlock,
-                address: metadata.manager_address
-            };
-
-            // Query for past logs
-            const response = await fetch(httpRpcUrl, {
-                method: 'POST',
-                headers: {
-                    'Content-Type': 'application/json',
-                },
-                body: JSON.stringify({
-                    jsonrpc: '2.0',
-                    method: 'eth_getLogs',
-                    params: [logParams],
-                    id: 1
-                })
-            });
-
-            const result = await response.json();
-            if (result.error) {
-                console.warn('Error fetching existing transactions:', result.error.message);
-                return;
-            }
-
-            const logs = result.result || [];
-            console.log('Found', logs.length, 'total logs');
-            
-            // For each log, get the full transaction and check if it's TO the manager contract
+            // The server indexes verifyAndEmit transactions per sender; page through them.
             const ourTransactions = [];
-            
-            for (const log of logs) {
-                try {
-                    // Get the full transaction details
-                    const txResponse = await fetch(httpRpcUrl, {
-                        method: 'POST',
-                        headers: {
-                            'Content-Type': 'application/json',
-                        },
-                        body: JSON.stringify({
-                            jsonrpc: '2.0',
-                            method: 'eth_getTransactionByHash',
-                            params: [log.transactionHash],
-                            id: 1
-                        })
+            const pageSize = 500;
+            for (let offset = 0; ; offset += pageSize) {
+                const response = await fetch(
+                    `/transactions?sender=${encodeURIComponent(this.account)}&offset=${offset}&limit=${pageSize}`
+                );
+                if (!response.ok) {
+                    throw new Error(`HTTP error! status: ${response.status}`);
+                }
+                const page = await response.json();
+                for (const tx of page.items) {
+                    ourTransactions.push({
+                        hash: tx.tx_hash,
+                        from: tx.sender,
+                        to: metadata.manager_address,
+                        blockNumber: tx.block_number,
+                        timestamp: tx.block_timestamp ? tx.block_timestamp * 1000 : null,
+                        status: tx.success ? 'confirmed' : 'failed'
                     });
-
-                    const txResult = await txResponse.json();
-                    if (txResult.result && txResult.result.to) {
-                        const txTo = txResult.result.to.toLowerCase();
-                        const managerAddress = metadata.manager_address.toLowerCase();
-                        const txFrom = txResult.result.from ? txResult.result.from.toLowerCase() : '';
-                        const ourAccount = this.account.toLowerCase();
-                        
-                        // Check if this transaction is TO the manager contract AND from our wallet
-                        if (txTo === managerAddress && txFrom === ourAccount) {
-                            ourTransactions.push({
-                                hash: log.transactionHash,
-                                from: txResult.result.from,
-                                to: txResult.result.to,
-                                blockNumber: log.blockNumber ? parseInt(log.blockNumber, 16) : null,
-                                timestamp: null, // Will be fetched separately if needed
-                                status: 'confirmed'
-                            });
-                        }
-                    }
-                } catch (txError) {
-                    console.warn('Error fetching transaction:', log.transactionHash, txError);
-                    continue;
                 }
+                if (offset + pageSize >= page.total) break;
             }
 
             console.log('Found', ourTransactions.length, 'transactions from our wallet to manager contract');
 
             // Add to transaction history (avoid duplicates)
             for (const tx of ourTransactions) {
-                if (!this.transactionHistory.find(t => t.hash === tx.hash)) {
+                const existing = this.transactionHistory.find(t => t.hash.toLowerCase() === tx.hash.toLowerCase());
+                if (!existing) {
                     this.transactionHistory.push(tx);
+                } else if (existing.status === 'pending') {
+                    Object.assign(existing, tx);
                 }
             }
 
@@ -1159,9 +1081,12 @@ class TaskMonitor {
     createProofRow(proof, rowNum) {
         const proofHex = this.formatBytes(proof.proof_bytes);
         const publicValuesHex = this.formatBytes(proof.public_values);
-        const publishedBadge = proof.is_published
-            ? `<span class="status-badge completed">Yes</span>`
-            : `<span class="status-badge pending">No</span>`;
+        const feeTitle = proof.fee_wei ? ` title="Fee paid: ${this.escapeHtml(proof.fee_wei)} wei"` : '';
+        const publishedBadge = proof.publication === 'pending'
+            ? `<span class="status-badge pending"${feeTitle}>Confirming</span>`
+            : proof.is_published
+                ? `<span class="status-badge completed"${feeTitle}>Yes</span>`
+                : `<span class="status-badge pending">No</span>`;
 
         const safeQueryId = this.escapeHtml(proof.query_id);
         const rowId = proof.query_id.replace(/[^a-zA-Z0-9]/g, '_');
@@ -1376,39 +1301,13 @@ class TaskMonitor {
 
     async loadFraudData() {
         try {
-            const graphqlEndpoint = 'https://fa7e5d08-286a-4511-9598-d4aa8ea9594b.squids.live/zk-feed@v1/api/graphql';
-            
-            const query = `
-                query {
-                    contractEventFraudFounds(limit: 100) {
-                        blockNumber
-                        peerId
-                        timestamp
-                    }
-                }
-            `;
-
-            const response = await fetch(graphqlEndpoint, {
-                method: 'POST',
-                headers: {
-                    'Content-Type': 'application/json',
-                },
-                body: JSON.stringify({
-                    query: query
-                })
-            });
+            const response = await fetch('/fraud-events?limit=100');
 
             if (!response.ok) {
                 throw new Error(`HTTP error! status: ${response.status}`);
             }
 
-            const result = await response.json();
-            
-            if (result.errors) {
-                throw new Error(`GraphQL error: ${result.errors[0].message}`);
-            }
-
-            this.fraudData = (result.data.contractEventFraudFounds || []).sort((a, b) => (b.blockNumber || 0) - (a.blockNumber || 0));
+            this.fraudData = await response.json();
             this.renderFraudData();
         } catch (error) {
             console.error('Failed to load fraud data:', error);
@@ -1430,7 +1329,7 @@ class TaskMonitor {
                         <path d="M8 12L11 15L16 9" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"/>
                     </svg>
                     <h3>No fraud events found</h3>
-                    <p>No fraud events data available from GraphQL endpoint</p>
+                    <p>No FraudFound events have been indexed yet</p>
                 </div>
             `;
             return;
@@ -1445,6 +1344,10 @@ class TaskMonitor {
                             <th>Block Number</th>
                             <th>Peer ID</th>
                             <th>Timestamp</th>
+                            <th>Query ID</th>
+                            <th>Sender</th>
+                            <th>Tx</th>
+                            <th>Status</th>
                         </tr>
                     </thead>
                     <tbody>
@@ -1467,10 +1370,8 @@ class TaskMonitor {
                 <div style="background: #fef2f2; border: 1px solid #fecaca; border-radius: 6px; padding: 12px; margin-top: 12px;">
                     <strong style="color: #991b1b; font-size: 13px;">Possible Issues:</strong>
                     <ul style="margin: 8px 0 0 16px; color: #6b7280; font-size: 12px;">
-                        <li>GraphQL endpoint is unreachable</li>
+                        <li>The server is unreachable</li>
                         <li>Network connectivity issues</li>
-                        <li>Query format or syntax error</li>
-                        <li>Server-side issues with the endpoint</li>
                     </ul>
                 </div>
                 <button class="refresh-btn" onclick="loadFraudData()" style="margin-top: 16px;">
@@ -1487,10 +1388,10 @@ class TaskMonitor {
     createFraudDataRow(item, rowNum) {
         // Format timestamp if it's a number (Unix timestamp) or use as-is
         let formattedTimestamp;
-        if (item.timestamp) {
+        if (item.timestamp_ms) {
             try {
                 // Try to parse as Unix timestamp (seconds or milliseconds)
-                const timestamp = parseInt(item.timestamp);
+                const timestamp = parseInt(item.timestamp_ms);
                 if (timestamp > 1e12) {
                     // Milliseconds
                     formattedTimestamp = new Date(timestamp).toLocaleString();
@@ -1499,20 +1400,25 @@ class TaskMonitor {
                     formattedTimestamp = new Date(timestamp * 1000).toLocaleString();
                 } else {
                     // Already formatted or unknown format
-                    formattedTimestamp = item.timestamp;
+                    formattedTimestamp = String(item.timestamp_ms);
                 }
             } catch {
-                formattedTimestamp = item.timestamp;
+                formattedTimestamp = String(item.timestamp_ms);
             }
         } else {
             formattedTimestamp = 'N/A';
         }
 
         // Format block number
-        const blockNumber = item.blockNumber ? item.blockNumber.toString() : 'N/A';
+        const blockNumber = item.block_number ? item.block_number.toString() : 'N/A';
         
         // Format peer ID (display full value)
-        let peerId = item.peerId || 'N/A';
+        let peerId = item.peer_id || 'N/A';
+
+        const queryId = item.query_id
+            ? `<span class="mono">${this.escapeHtml(item.query_id)}</span>`
+            : `<span class="text-muted" title="${this.escapeHtml(item.resolution_error || '')}">unresolved</span>`;
+        const statusClass = item.status === 'final' ? 'completed' : 'pending';
         
         return `
             <tr>
@@ -1520,6 +1426,10 @@ class TaskMonitor {
                 <td>${blockNumber}</td>
                 <td class="mono">${this.escapeHtml(peerId)}</td>
                 <td class="text-muted">${this.escapeHtml(formattedTimestamp)}</td>
+                <td>${queryId}</td>
+                <td class="mono">${this.escapeHtml(item.sender || 'N/A')}</td>
+                <td class="mono">${this.escapeHtml(item.tx_hash)}</td>
+                <td><span class="status-badge ${statusClass}">${this.escapeHtml(item.status)}</span></td>
             </tr>
         `;
     }